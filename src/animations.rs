@@ -3,7 +3,7 @@ pub struct AnimationPlugin;
 
 impl Plugin for AnimationPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, animate_sprite);
+        app.add_systems(Update, (animate_sprite, move_along_path, float_away));
     }
 }
 
@@ -40,3 +40,75 @@ fn animate_sprite(
         }
     }
 }
+
+/// Moves the entity cell by cell through the positions of the path
+#[derive(Component)]
+pub struct MoveAlongPath {
+    path: Vec<Vec3>,
+    timer: Timer,
+}
+
+impl MoveAlongPath {
+    pub fn new(path: Vec<Vec3>, seconds_per_step: f32) -> Self {
+        let steps = path.len().saturating_sub(1);
+        Self {
+            path,
+            timer: Timer::from_seconds(seconds_per_step * steps as f32, TimerMode::Once),
+        }
+    }
+}
+
+fn move_along_path(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut MoveAlongPath, &mut Transform)>,
+) {
+    for (entity, mut movement, mut transform) in &mut query {
+        movement.timer.tick(time.delta());
+        let Some(last) = movement.path.last().copied() else {
+            commands.entity(entity).remove::<MoveAlongPath>();
+            continue;
+        };
+        if movement.timer.is_finished() {
+            transform.translation = last;
+            commands.entity(entity).remove::<MoveAlongPath>();
+            continue;
+        }
+        let progress = movement.timer.fraction() * (movement.path.len() - 1) as f32;
+        let step = progress as usize;
+        transform.translation = movement.path[step].lerp(movement.path[step + 1], progress.fract());
+    }
+}
+
+/// Moves the text up while it fades out, the entity is despawned at the end
+#[derive(Component)]
+pub struct FloatAway {
+    distance: f32,
+    timer: Timer,
+}
+
+impl FloatAway {
+    pub fn new(distance: f32, seconds: f32) -> Self {
+        Self {
+            distance,
+            timer: Timer::from_seconds(seconds, TimerMode::Once),
+        }
+    }
+}
+
+fn float_away(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut FloatAway, &mut Transform, &mut TextColor)>,
+) {
+    for (entity, mut float, mut transform, mut color) in &mut query {
+        let before = float.timer.fraction();
+        float.timer.tick(time.delta());
+        if float.timer.is_finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        transform.translation.y += (float.timer.fraction() - before) * float.distance;
+        color.0.set_alpha(1.0 - float.timer.fraction());
+    }
+}
@@ -0,0 +1,226 @@
+use std::{collections::BTreeMap, fs, path::Path, process::ExitCode};
+
+use advance_bevy_wars::board::{
+    Map, MapLoaderError, MapPreview, Rules, parse_map_with_version, validate_map, write_map,
+    write_map_v2,
+};
+
+const USAGE: &str = "Usage: abw-map <command> [arguments]
+
+Commands:
+    validate <file>...                      Check that the maps can be parsed and played
+    stats <file>                            Print terrain, buildings and units per owner
+    convert <file> <version> [-o <output>]  Write the map using the given format version
+    format <file> [-o <output>]             Normalize the map keeping its format version
+    render <file> -o <output.png> [--size <pixels>] [--assets <dir>]
+                                            Draw the map into a PNG image
+";
+
+fn read_map(path: &str) -> Result<(Map, i64), String> {
+    let content = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+    parse_map_with_version(&content).map_err(|err| match err {
+        MapLoaderError::Invalid(diagnostics) => format!("{path}:\n{diagnostics}"),
+        err => format!("{path}: {err}"),
+    })
+}
+
+fn write_output(content: &str, output: Option<&str>) -> Result<(), String> {
+    match output {
+        Some(path) => fs::write(path, content).map_err(|err| format!("{path}: {err}")),
+        None => {
+            print!("{content}");
+            Ok(())
+        }
+    }
+}
+
+fn serialize(map: &Map, version: i64) -> Result<String, String> {
+    match version {
+        1 => Ok(write_map(map)),
+        2 => Ok(write_map_v2(map)),
+        version => Err(format!("Unsupported map version {version}")),
+    }
+}
+
+/// Splits the arguments between the positional ones and the values of the options
+fn parse_options<'a>(
+    args: &'a [String],
+    options: &[&str],
+) -> Result<(Vec<&'a str>, BTreeMap<String, &'a str>), String> {
+    let mut positional = Vec::new();
+    let mut values = BTreeMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let option = match arg.as_str() {
+            "-o" => "--output",
+            arg => arg,
+        };
+        if options.contains(&option) {
+            let Some(value) = args.next() else {
+                return Err(format!("Missing value after {arg}"));
+            };
+            values.insert(option.to_string(), value.as_str());
+        } else {
+            positional.push(arg.as_str());
+        }
+    }
+    Ok((positional, values))
+}
+
+fn validate(files: &[String]) -> Result<bool, String> {
+    if files.is_empty() {
+        return Err("Missing map file".into());
+    }
+    let mut valid = true;
+    for path in files {
+        match read_map(path) {
+            Err(err) => {
+                eprintln!("{err}");
+                valid = false;
+            }
+            Ok((map, _version)) => {
+                let diagnostics = validate_map(&map, Rules::builtin());
+                for diagnostic in diagnostics.0.iter() {
+                    eprintln!("{path}: {diagnostic}");
+                }
+                if diagnostics.has_errors() {
+                    valid = false;
+                } else {
+                    println!("{path}: ok");
+                }
+            }
+        }
+    }
+    Ok(valid)
+}
+
+fn print_counts(title: &str, counts: &BTreeMap<String, usize>) {
+    println!("{title}:");
+    for (name, count) in counts {
+        println!("    {name:<24} {count}");
+    }
+}
+
+fn stats(args: &[String]) -> Result<bool, String> {
+    let [path] = args else {
+        return Err("stats expects a single map file".into());
+    };
+    let (map, version) = read_map(path)?;
+
+    let mut terrain = BTreeMap::new();
+    let mut buildings = BTreeMap::new();
+    let mut units = BTreeMap::new();
+    for cell in map.cells.iter() {
+        *terrain.entry(format!("{:?}", cell.terrain)).or_default() += 1;
+        if let Some(building) = &cell.building {
+            let owner = match building.owner.0 {
+                0 => "neutral".to_string(),
+                owner => format!("player {owner}"),
+            };
+            *buildings
+                .entry(format!("{owner} {:?}", building.build_type))
+                .or_default() += 1;
+        }
+        if let Some(unit) = &cell.unit {
+            *units
+                .entry(format!("player {} {:?}", unit.owner.0, unit.unit_type))
+                .or_default() += 1;
+        }
+    }
+
+    println!("{path} (version {version})");
+    if !map.metadata.name.is_empty() {
+        println!("Name: {}", map.metadata.name);
+    }
+    if !map.metadata.author.is_empty() {
+        println!("Author: {}", map.metadata.author);
+    }
+    println!("Size: {}x{}", map.width(), map.height());
+    println!("Players: {}", map.players.len());
+    print_counts("Terrain", &terrain);
+    print_counts("Buildings", &buildings);
+    print_counts("Units", &units);
+    Ok(true)
+}
+
+fn convert(args: &[String]) -> Result<bool, String> {
+    let (positional, options) = parse_options(args, &["--output"])?;
+    let output = options.get("--output").copied();
+    let [path, version] = positional[..] else {
+        return Err("convert expects a map file and a version".into());
+    };
+    let version: i64 = version
+        .parse()
+        .map_err(|_err| format!("Invalid version {version}"))?;
+    let (map, _version) = read_map(path)?;
+    write_output(&serialize(&map, version)?, output)?;
+    Ok(true)
+}
+
+fn normalize(args: &[String]) -> Result<bool, String> {
+    let (positional, options) = parse_options(args, &["--output"])?;
+    let output = options.get("--output").copied();
+    let [path] = positional[..] else {
+        return Err("format expects a single map file".into());
+    };
+    let (map, version) = read_map(path)?;
+    write_output(&serialize(&map, version)?, output)?;
+    Ok(true)
+}
+
+fn render(args: &[String]) -> Result<bool, String> {
+    let (positional, options) = parse_options(args, &["--output", "--size", "--assets"])?;
+    let [path] = positional[..] else {
+        return Err("render expects a single map file".into());
+    };
+    let Some(output) = options.get("--output") else {
+        return Err("render needs the output image, use -o <output.png>".into());
+    };
+    let assets = options.get("--assets").copied().unwrap_or("assets");
+    let (map, _version) = read_map(path)?;
+
+    let preview = MapPreview::load(Path::new(assets)).map_err(|err| format!("{assets}: {err}"))?;
+    let image = match options.get("--size") {
+        Some(size) => {
+            let size = size
+                .parse()
+                .map_err(|_err| format!("Invalid size {size}"))?;
+            preview.thumbnail(&map, size)
+        }
+        None => preview.render(&map),
+    };
+    image
+        .save(output)
+        .map_err(|err| format!("{output}: {err}"))?;
+    Ok(true)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some((command, args)) = args.split_first() else {
+        eprint!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+
+    let result = match command.as_str() {
+        "validate" => validate(args),
+        "stats" => stats(args),
+        "convert" => convert(args),
+        "format" => normalize(args),
+        "render" => render(args),
+        "help" | "-h" | "--help" => {
+            print!("{USAGE}");
+            Ok(true)
+        }
+        command => Err(format!("Unknown command {command}\n\n{USAGE}")),
+    };
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use bevy::prelude::*;
+
+use crate::{
+    board::{board::Board, direction::Direction},
+    interactive::Life,
+};
+
+/// What a unit can do once it moves
+#[derive(Message, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UnitAction {
+    Fire,
+    Capture,
+    Load,
+    Join,
+    Supply,
+    Wait,
+}
+
+impl UnitAction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Fire => "Fire",
+            Self::Capture => "Capture",
+            Self::Load => "Load",
+            Self::Join => "Join",
+            Self::Supply => "Supply",
+            Self::Wait => "Wait",
+        }
+    }
+}
+
+impl Board {
+    /// The unit can get into the transport in the position
+    pub fn can_load(&self, unit_pos: &UVec2, pos: &UVec2) -> bool {
+        let Some(unit) = self.units.get(unit_pos) else {
+            return false;
+        };
+        unit_pos != pos
+            && self.units.get(pos).is_some_and(|transport| {
+                transport.owner == unit.owner
+                    && transport.unit_type.can_carry(unit.unit_type, self.rules())
+                    && self.cargo(pos).len() < transport.unit_type.transport_capacity()
+            })
+    }
+
+    /// The supply unit in the position refills the allies next to it, returns their
+    /// positions
+    pub fn supply_units(&mut self, pos: UVec2) -> Vec<UVec2> {
+        let Some(supplier) = self.units.get(&pos).copied() else {
+            return vec![];
+        };
+        if !supplier.unit_type.supplies() {
+            return vec![];
+        }
+        let allies: Vec<UVec2> = Direction::ADJACENT
+            .iter()
+            .filter_map(|dir| dir.move_point(&pos))
+            .filter(|other| {
+                self.units
+                    .get(other)
+                    .is_some_and(|unit| unit.owner == supplier.owner)
+            })
+            .collect();
+        for ally in allies.iter() {
+            self.resupply(*ally);
+        }
+        allies
+    }
+
+    /// The unit in the position is a damaged ally of the same type
+    pub fn can_join(&self, unit_pos: &UVec2, pos: &UVec2) -> bool {
+        let Some(unit) = self.units.get(unit_pos) else {
+            return false;
+        };
+        unit_pos != pos
+            && self.units.get(pos).is_some_and(|other| {
+                other.owner == unit.owner
+                    && other.unit_type == unit.unit_type
+                    && other.health != Life::new()
+            })
+    }
+
+    /// Actions of the unit in `from` if it moves to `to`, the board should not be
+    /// updated with the move yet
+    pub fn unit_actions(&self, from: UVec2, to: UVec2) -> Vec<UnitAction> {
+        self.unit_actions_seeing(from, to, |_| true)
+    }
+
+    /// Same as [`Board::unit_actions`] but the unit can only fire at the enemies in
+    /// visible cells
+    pub fn unit_actions_seeing(
+        &self,
+        from: UVec2,
+        to: UVec2,
+        is_visible: impl Fn(&UVec2) -> bool,
+    ) -> Vec<UnitAction> {
+        let Some(unit) = self.units.get(&from) else {
+            return vec![];
+        };
+        if from != to && self.units.contains_key(&to) {
+            return [
+                (self.can_load(&from, &to), UnitAction::Load),
+                (self.can_join(&from, &to), UnitAction::Join),
+            ]
+            .into_iter()
+            .filter_map(|(valid, action)| valid.then_some(action))
+            .collect();
+        }
+
+        let mut actions = vec![];
+        if !self
+            .attack_targets_seeing(unit, to, from != to, is_visible)
+            .is_empty()
+        {
+            actions.push(UnitAction::Fire);
+        }
+        if unit.unit_type.can_capture()
+            && self
+                .buildings
+                .get(&to)
+                .is_some_and(|building| building.owner != unit.owner)
+        {
+            actions.push(UnitAction::Capture);
+        }
+        if unit.unit_type.supplies()
+            && Direction::ADJACENT.iter().any(|dir| {
+                dir.move_point(&to)
+                    .filter(|pos| *pos != from)
+                    .and_then(|pos| self.units.get(&pos))
+                    .is_some_and(|other| other.owner == unit.owner)
+            })
+        {
+            actions.push(UnitAction::Supply);
+        }
+        actions.push(UnitAction::Wait);
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{
+        map::UnitType,
+        test_utils::{make_board, row_map},
+    };
+
+    const PLAINS: &[&str] = &["p"; 5];
+
+    #[test]
+    fn test_wait() {
+        let board = make_board(&row_map(PLAINS, "", "1x1 = {type=\"tank\", owner=1}", ""));
+
+        assert_eq!(
+            board.unit_actions(uvec2(0, 0), uvec2(2, 0)),
+            vec![UnitAction::Wait]
+        );
+        assert_eq!(board.unit_actions(uvec2(1, 0), uvec2(2, 0)), vec![]);
+    }
+
+    #[test]
+    fn test_fire() {
+        let board = make_board(&row_map(
+            PLAINS,
+            "",
+            "1x1 = {type=\"tank\", owner=1}
+            2x1 = {type=\"artillery\", owner=1}
+            5x1 = {type=\"infantry\", owner=2}",
+            "",
+        ));
+
+        assert_eq!(
+            board.unit_actions(uvec2(0, 0), uvec2(3, 0)),
+            vec![UnitAction::Fire, UnitAction::Wait]
+        );
+        assert_eq!(
+            board.unit_actions(uvec2(0, 0), uvec2(0, 0)),
+            vec![UnitAction::Wait]
+        );
+        assert_eq!(
+            board.unit_actions(uvec2(1, 0), uvec2(1, 0)),
+            vec![UnitAction::Fire, UnitAction::Wait]
+        );
+        assert_eq!(
+            board.unit_actions(uvec2(1, 0), uvec2(2, 0)),
+            vec![UnitAction::Wait],
+            "Indirect units can not fire after moving"
+        );
+    }
+
+    #[test]
+    fn test_capture() {
+        let board = make_board(&row_map(
+            PLAINS,
+            "2x1 = {type=\"city\"}
+            3x1 = {type=\"city\", owner=1}",
+            "1x1 = {type=\"infantry\", owner=1}
+            4x1 = {type=\"tank\", owner=1}",
+            "",
+        ));
+
+        assert_eq!(
+            board.unit_actions(uvec2(0, 0), uvec2(1, 0)),
+            vec![UnitAction::Capture, UnitAction::Wait]
+        );
+        assert_eq!(
+            board.unit_actions(uvec2(0, 0), uvec2(2, 0)),
+            vec![UnitAction::Wait]
+        );
+        assert_eq!(
+            board.unit_actions(uvec2(3, 0), uvec2(1, 0)),
+            vec![UnitAction::Wait]
+        );
+    }
+
+    #[test]
+    fn test_load_join_and_supply() {
+        let board = make_board(&row_map(
+            PLAINS,
+            "",
+            "1x1 = {type=\"infantry\", owner=1}
+            2x1 = {type=\"apc\", owner=1}
+            3x1 = {type=\"infantry\", owner=1, life=40}
+            4x1 = {type=\"tank\", owner=1}",
+            "",
+        ));
+
+        assert_eq!(
+            board.unit_actions(uvec2(0, 0), uvec2(1, 0)),
+            vec![UnitAction::Load]
+        );
+        assert_eq!(
+            board.unit_actions(uvec2(0, 0), uvec2(2, 0)),
+            vec![UnitAction::Join]
+        );
+        assert_eq!(board.unit_actions(uvec2(0, 0), uvec2(3, 0)), vec![]);
+        assert_eq!(
+            board.unit_actions(uvec2(1, 0), uvec2(1, 0)),
+            vec![UnitAction::Supply, UnitAction::Wait]
+        );
+    }
+
+    #[test]
+    fn test_supply_units() {
+        let mut board = make_board(&row_map(
+            PLAINS,
+            "",
+            "1x1 = {type=\"tank\", owner=1}
+            2x1 = {type=\"apc\", owner=1}
+            3x1 = {type=\"tank\", owner=2}",
+            "",
+        ));
+        for pos in [uvec2(0, 0), uvec2(2, 0)] {
+            let unit = board.units.get_mut(&pos).unwrap();
+            unit.ammo_used = 3;
+            unit.fuel_used = 20;
+        }
+
+        assert_eq!(board.supply_units(uvec2(1, 0)), vec![uvec2(0, 0)]);
+        let tank = board.units[&uvec2(0, 0)];
+        assert_eq!(
+            board.ammo_left(&tank),
+            board.rules().unit(UnitType::Tank).ammo
+        );
+        assert_eq!(
+            board.fuel_left(&tank),
+            board.rules().unit(UnitType::Tank).fuel
+        );
+        assert_eq!(
+            board.units[&uvec2(2, 0)].ammo_used,
+            3,
+            "Enemies are not supplied"
+        );
+        assert_eq!(
+            board.supply_units(uvec2(0, 0)),
+            vec![],
+            "Only supply units do it"
+        );
+    }
+}
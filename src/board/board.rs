@@ -3,17 +3,20 @@ use std::{
 };
 
 use assets_helper::AssetsTrait;
-use auto_tiler::{AutoTiler, BoardTrait, Neighbor};
-use bevy::{prelude::*, render::render_resource::encase::private::Length};
+use auto_tiler::{AsMask, AutoTiler, BoardTrait, Neighbor};
+use bevy::prelude::*;
 
 use crate::{
     assets::FileAssets,
     board::{
+        combat::hit_points,
         direction::Direction,
-        map::{Building, Map, Terrain, Unit},
+        map::{Building, Map, PlayerColor, Terrain, Unit, UnitType},
+        rules::Rules,
         terrain::TileTerrain,
+        victory::{DefeatReason, PlayerStats},
     },
-    interactive::BoardPos,
+    interactive::{BoardPos, Capturable, CapturePoints, Income, Life, Owner, PlayerId},
     matrix::Matrix,
 };
 
@@ -26,11 +29,66 @@ pub struct Tiler(pub AutoTiler<TileTerrain, UVec2>);
 #[derive(Resource)]
 pub struct Board {
     map: Map,
+    rules: Rules,
     layers: Vec<BoardLayer>,
     pub buildings: HashMap<UVec2, Building>,
     pub units: HashMap<UVec2, Unit>,
+    /// Positions of the units that already moved this turn
+    moved: HashSet<UVec2>,
+    /// Units carried by the transport in each position
+    cargo: HashMap<UVec2, Vec<Unit>>,
+    /// Buildings being captured, the progress is lost when the capturer leaves
+    captures: HashMap<UVec2, Capturable>,
+    /// Capture left by the last move, it is restored if the move is undone
+    abandoned_capture: Option<(UVec2, Capturable)>,
+    /// Money of each player to produce and repair units
+    funds: HashMap<PlayerId, u32>,
+    stats: HashMap<PlayerId, PlayerStats>,
+    /// Players out of the match
+    defeated: HashMap<PlayerId, DefeatReason>,
 }
 
+/// Life healed every turn to the units on buildings of their owner
+const REPAIR_LIFE: u8 = 20;
+
+/// What the player got at the start of the turn
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Upkeep {
+    pub income: u32,
+    /// Units repaired with the life they got
+    pub repairs: Vec<(UVec2, u8)>,
+    pub repair_cost: u32,
+    /// Units that got their fuel and ammo back
+    pub resupplied: Vec<UVec2>,
+}
+
+/// Result of a unit capturing the building under it
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CaptureOutcome {
+    /// Points still needed to capture the building
+    InProgress(CapturePoints),
+    /// The building changed hands, with the owner it had
+    Captured(Owner),
+}
+
+#[derive(Component)]
+pub struct TerrainTile;
+
+/// Texture and atlas of the terrain sheet, kept to draw the tiles again when the
+/// terrain changes
+#[derive(Resource)]
+pub struct TerrainSprites {
+    texture: Handle<Image>,
+    layout: Handle<TextureAtlasLayout>,
+}
+
+/// Sent after the terrain of a cell changed, so its tiles are drawn again
+#[derive(Message)]
+pub struct TerrainChanged(pub UVec2);
+
+#[derive(Message)]
+pub struct BuildingCaptured(pub UVec2);
+
 #[derive(Component)]
 pub struct BuildingCompoent;
 #[derive(Component)]
@@ -44,6 +102,8 @@ impl Default for Board {
 
 struct BoardLayer {
     tiles: HashMap<UVec2, TileTerrain>,
+    /// Terrain used as neighbors, when the tiles of the layer depend on the rest
+    surroundings: Option<HashMap<UVec2, TileTerrain>>,
 }
 
 impl BoardLayer {
@@ -63,8 +123,76 @@ impl BoardLayer {
             }
         }
 
-        Self { tiles }
+        Self {
+            tiles,
+            surroundings: None,
+        }
+    }
+
+    fn with_surroundings(mut self, map: &Matrix<TileTerrain>) -> Self {
+        self.surroundings = Some(
+            map.keys()
+                .into_iter()
+                .map(|(x, y)| (uvec2(x as u32, y as u32), map[(x, y)]))
+                .collect(),
+        );
+        self
+    }
+}
+
+/// Bridges are drawn over the water they cross
+fn water_under_bridges(map: &Matrix<TileTerrain>) -> Matrix<TileTerrain> {
+    let mut ground = map.clone();
+    for pos in map.keys() {
+        if map[pos] != TileTerrain::Bridge {
+            continue;
+        }
+        let coord = uvec2(pos.0 as u32, pos.1 as u32);
+        let next_to_river = Direction::ADJACENT.iter().any(|dir| {
+            dir.move_point(&coord)
+                .and_then(|neighbor| map.get(neighbor.x as usize, neighbor.y as usize))
+                == Some(&TileTerrain::River)
+        });
+        ground[pos] = if next_to_river {
+            TileTerrain::River
+        } else {
+            TileTerrain::Sea
+        };
     }
+    ground
+}
+
+fn build_layers(map: &Map) -> Vec<BoardLayer> {
+    let tiles: Matrix<TileTerrain> = map.into();
+    vec![
+        BoardLayer::build(
+            &water_under_bridges(&tiles),
+            &HashSet::from([
+                TileTerrain::Plain,
+                TileTerrain::Sea,
+                TileTerrain::Road,
+                TileTerrain::Beach,
+                TileTerrain::River,
+            ]),
+            Some(TileTerrain::Plain),
+        ),
+        BoardLayer::build(
+            &tiles,
+            &HashSet::from([TileTerrain::Mountain, TileTerrain::Forest]),
+            None,
+        ),
+        BoardLayer::build(
+            &tiles,
+            &HashSet::from([
+                TileTerrain::Bridge,
+                TileTerrain::Wall,
+                TileTerrain::BreakableWall,
+                TileTerrain::DamagedWall,
+            ]),
+            None,
+        )
+        .with_surroundings(&tiles),
+    ]
 }
 
 impl BoardTrait<TileTerrain, UVec2, Direction> for BoardLayer {
@@ -77,11 +205,13 @@ impl BoardTrait<TileTerrain, UVec2, Direction> for BoardLayer {
         pos: &UVec2,
         directions: &[Direction],
     ) -> Vec<Neighbor<TileTerrain, Direction>> {
+        let terrain = self.surroundings.as_ref().unwrap_or(&self.tiles);
         directions
             .iter()
             .filter_map(|dir| Some((dir, dir.move_point(pos)?)))
             .filter_map(|(dir, neighbor_pos)| {
-                self.get(&neighbor_pos)
+                terrain
+                    .get(&neighbor_pos)
                     .map(|terrain| Neighbor::new(*terrain, *dir))
             })
             .collect()
@@ -89,27 +219,18 @@ impl BoardTrait<TileTerrain, UVec2, Direction> for BoardLayer {
 }
 
 impl Board {
-    fn new(map: Map) -> Self {
-        let layers = {
-            let tiles: Matrix<TileTerrain> = (&map).into();
-            [
-                BoardLayer::build(
-                    &tiles,
-                    &HashSet::from([
-                        TileTerrain::Plain,
-                        TileTerrain::Sea,
-                        TileTerrain::Road,
-                        TileTerrain::Beach,
-                    ]),
-                    Some(TileTerrain::Plain),
-                ),
-                BoardLayer::build(
-                    &tiles,
-                    &HashSet::from([TileTerrain::Mountain, TileTerrain::Forest]),
-                    None,
-                ),
-            ]
-        };
+    pub fn new(map: Map) -> Self {
+        Self::with_rules(map, Rules::builtin().clone())
+    }
+
+    /// Creates the board, the stats of the units and buildings come from the rules
+    pub fn with_rules(map: Map, rules: Rules) -> Self {
+        let layers = build_layers(&map);
+        let funds = map
+            .players
+            .iter()
+            .map(|player| (player.id, player.funds))
+            .collect();
 
         let buildings = map.cells.keys().iter().map(|pos| (pos, map.cells.get(pos.0, pos.1))).filter_map(|(pos, data)| {
             match data {
@@ -134,15 +255,440 @@ impl Board {
 
         Self {
             map,
-            layers: layers.into(),
+            rules,
+            layers,
             buildings,
             units,
+            moved: HashSet::new(),
+            cargo: HashMap::new(),
+            captures: HashMap::new(),
+            abandoned_capture: None,
+            funds,
+            stats: HashMap::new(),
+            defeated: HashMap::new(),
         }
     }
     pub fn get_size(&self) -> (usize, usize) {
         self.map.get_size()
     }
 
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    /// Funds the building gives every day, the map can override the rules
+    pub fn income(&self, building: &Building) -> Income {
+        building
+            .income
+            .unwrap_or(self.rules.building(building.build_type).income)
+    }
+
+    pub fn layers_len(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Coordinates in the terrain sheet of the tile to draw in the layer
+    pub fn get_tile(
+        &self,
+        auto_tiler: &AutoTiler<TileTerrain, UVec2>,
+        layer: usize,
+        pos: UVec2,
+    ) -> Option<UVec2> {
+        auto_tiler.get_tile::<UVec2, Direction>(self.layers.get(layer)?, pos)
+    }
+
+    /// Every unit can move again, a move waiting to be confirmed can't be undone
+    pub fn start_turn(&mut self) {
+        self.moved.clear();
+        self.abandoned_capture = None;
+    }
+
+    pub fn funds(&self, player: PlayerId) -> u32 {
+        self.funds.get(&player).copied().unwrap_or_default()
+    }
+
+    pub fn stats(&self, player: PlayerId) -> PlayerStats {
+        self.stats.get(&player).copied().unwrap_or_default()
+    }
+
+    pub fn stats_mut(&mut self, player: PlayerId) -> &mut PlayerStats {
+        self.stats.entry(player).or_default()
+    }
+
+    pub fn defeat_reason(&self, player: PlayerId) -> Option<DefeatReason> {
+        self.defeated.get(&player).copied()
+    }
+
+    /// Takes the player out of the match, its units are removed without counting as
+    /// lost and its buildings become neutral. Returns the positions of those
+    /// buildings.
+    pub fn defeat(&mut self, player: PlayerId, reason: DefeatReason) -> Vec<UVec2> {
+        if self.defeated.contains_key(&player) {
+            return vec![];
+        }
+        self.defeated.insert(player, reason);
+        let positions: Vec<UVec2> = self
+            .units
+            .iter()
+            .filter(|(_, unit)| unit.owner.0 == player)
+            .map(|(pos, _)| *pos)
+            .collect();
+        for pos in positions {
+            self.clear_unit(pos);
+        }
+        let mut neutral = vec![];
+        for (pos, building) in self.buildings.iter_mut() {
+            if building.owner.0 == player {
+                building.owner = Owner(0);
+                neutral.push(*pos);
+            }
+        }
+        neutral
+    }
+
+    /// Takes the money from the player, returns false when there is not enough
+    pub fn spend(&mut self, player: PlayerId, amount: u32) -> bool {
+        let funds = self.funds.entry(player).or_default();
+        if *funds < amount {
+            return false;
+        }
+        *funds -= amount;
+        true
+    }
+
+    /// The player collects the income of the buildings and then pays the repairs
+    /// of the units on the buildings that can repair them, which also refill their
+    /// fuel and ammo for free. Units are repaired less when there is not enough
+    /// money.
+    pub fn upkeep(&mut self, player: PlayerId) -> Upkeep {
+        let mut upkeep = Upkeep {
+            income: self
+                .buildings
+                .values()
+                .filter(|building| building.owner.0 == player)
+                .map(|building| self.income(building).0)
+                .sum(),
+            ..default()
+        };
+        *self.funds.entry(player).or_default() += upkeep.income;
+        self.stats_mut(player).income += upkeep.income;
+
+        let mut positions: Vec<UVec2> = self
+            .units
+            .iter()
+            .filter(|(pos, unit)| {
+                unit.owner.0 == player
+                    && self.buildings.get(pos).is_some_and(|building| {
+                        building.owner == unit.owner
+                            && self
+                                .rules
+                                .building(building.build_type)
+                                .repairs
+                                .contains(&self.rules.unit(unit.unit_type).class())
+                    })
+            })
+            .map(|(pos, _)| *pos)
+            .collect();
+        positions.sort_by_key(|pos| (pos.y, pos.x));
+        for pos in positions {
+            self.resupply(pos);
+            upkeep.resupplied.push(pos);
+            let unit = self.units[&pos];
+            let cost_per_life = self.rules.unit(unit.unit_type).cost / 100;
+            let affordable = self
+                .funds(player)
+                .checked_div(cost_per_life)
+                .unwrap_or(u32::MAX)
+                .min(REPAIR_LIFE as u32) as u8;
+            let healed = Life::new().0.saturating_sub(unit.health.0).min(affordable);
+            if healed == 0 {
+                continue;
+            }
+            let cost = healed as u32 * cost_per_life;
+            self.spend(player, cost);
+            if let Some(unit) = self.units.get_mut(&pos) {
+                unit.health = Life(unit.health.0 + healed);
+            }
+            upkeep.repairs.push((pos, healed));
+            upkeep.repair_cost += cost;
+        }
+        upkeep
+    }
+
+    /// Units the building in the position can produce
+    pub fn buildable_units(&self, pos: &UVec2) -> Vec<UnitType> {
+        let Some(building) = self.buildings.get(pos) else {
+            return vec![];
+        };
+        let builds = &self.rules.building(building.build_type).builds;
+        UnitType::ALL
+            .into_iter()
+            .filter(|unit_type| builds.contains(&self.rules.unit(*unit_type).class()))
+            .collect()
+    }
+
+    /// If the player owns the building, it is free and there is money for the unit
+    pub fn can_build(&self, pos: &UVec2, unit_type: UnitType, player: PlayerId) -> bool {
+        self.buildings
+            .get(pos)
+            .is_some_and(|building| building.owner.0 == player)
+            && !self.units.contains_key(pos)
+            && self.buildable_units(pos).contains(&unit_type)
+            && self.funds(player) >= self.rules.unit(unit_type).cost
+    }
+
+    /// Buys the unit in the building, it can't move until the next turn
+    pub fn build_unit(&mut self, pos: UVec2, unit_type: UnitType, player: PlayerId) -> bool {
+        if !self.can_build(&pos, unit_type, player) {
+            return false;
+        }
+        let stats = self.rules.unit(unit_type);
+        let unit = Unit {
+            owner: Owner(player),
+            health: Life::new(),
+            unit_type,
+            ammo_used: 0,
+            fuel_used: 0,
+        };
+        self.spend(player, stats.cost);
+        self.units.insert(pos, unit);
+        self.moved.insert(pos);
+        self.stats_mut(player).units_built += 1;
+        true
+    }
+
+    pub fn has_moved(&self, pos: &UVec2) -> bool {
+        self.moved.contains(pos)
+    }
+
+    /// Moves the unit and marks it as moved this turn, returns false when there is
+    /// no unit to move, it already moved or the destination is taken
+    pub fn move_unit(&mut self, from: UVec2, to: UVec2) -> bool {
+        if self.moved.contains(&from) || (from != to && self.units.contains_key(&to)) {
+            return false;
+        }
+        let Some(unit) = self.units.remove(&from) else {
+            return false;
+        };
+        self.units.insert(to, unit);
+        self.moved.insert(to);
+        if let Some(cargo) = self.cargo.remove(&from) {
+            self.cargo.insert(to, cargo);
+        }
+        if from != to {
+            self.abandoned_capture = self.captures.remove(&from).map(|capture| (from, capture));
+        }
+        true
+    }
+
+    /// Takes back a move that was not confirmed yet, so the unit can move again
+    pub fn undo_move(&mut self, from: UVec2, to: UVec2) -> bool {
+        if !self.moved.contains(&to) || (from != to && self.units.contains_key(&from)) {
+            return false;
+        }
+        let Some(unit) = self.units.remove(&to) else {
+            return false;
+        };
+        self.moved.remove(&to);
+        self.units.insert(from, unit);
+        if let Some(cargo) = self.cargo.remove(&to) {
+            self.cargo.insert(from, cargo);
+        }
+        if let Some((pos, capture)) = self.abandoned_capture.take()
+            && pos == from
+        {
+            self.captures.insert(pos, capture);
+        }
+        true
+    }
+
+    pub fn cargo(&self, pos: &UVec2) -> &[Unit] {
+        self.cargo.get(pos).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// The unit in `from` gets into the transport in `to`
+    pub fn load_unit(&mut self, from: UVec2, to: UVec2) -> bool {
+        if self.moved.contains(&from) || !self.can_load(&from, &to) {
+            return false;
+        }
+        let Some(unit) = self.units.remove(&from) else {
+            return false;
+        };
+        self.cargo.entry(to).or_default().push(unit);
+        self.captures.remove(&from);
+        true
+    }
+
+    /// The unit in `from` joins the damaged ally in `to`, adding up their life
+    pub fn join_units(&mut self, from: UVec2, to: UVec2) -> bool {
+        if self.moved.contains(&from) || !self.can_join(&from, &to) {
+            return false;
+        }
+        let Some(unit) = self.units.remove(&from) else {
+            return false;
+        };
+        let Some(target) = self.units.get_mut(&to) else {
+            return false;
+        };
+        target.health = Life(target.health.0.saturating_add(unit.health.0).min(Life::new().0));
+        self.moved.insert(to);
+        self.captures.remove(&from);
+        true
+    }
+
+    /// Shots of the main weapon the unit has left
+    pub fn ammo_left(&self, unit: &Unit) -> u32 {
+        self.rules
+            .unit(unit.unit_type)
+            .ammo
+            .saturating_sub(unit.ammo_used)
+    }
+
+    /// Fuel the unit has left, it burns one per cell moved
+    pub fn fuel_left(&self, unit: &Unit) -> u32 {
+        self.rules
+            .unit(unit.unit_type)
+            .fuel
+            .saturating_sub(unit.fuel_used)
+    }
+
+    /// The unit in the position burns the fuel of the cells it moved
+    pub fn burn_fuel(&mut self, pos: UVec2, cells: u32) {
+        if let Some(unit) = self.units.get_mut(&pos) {
+            unit.fuel_used += cells;
+        }
+    }
+
+    /// Refills the fuel and ammo of the unit in the position
+    pub fn resupply(&mut self, pos: UVec2) -> bool {
+        let Some(unit) = self.units.get_mut(&pos) else {
+            return false;
+        };
+        unit.ammo_used = 0;
+        unit.fuel_used = 0;
+        true
+    }
+
+    /// Takes life from the unit in the position, it is destroyed along with its
+    /// cargo when it runs out of life. Returns whether the unit survived.
+    pub fn hit_unit(&mut self, pos: UVec2, damage: u8) -> bool {
+        let Some(unit) = self.units.get_mut(&pos) else {
+            return false;
+        };
+        unit.health = Life(unit.health.0.saturating_sub(damage));
+        if unit.health.0 > 0 {
+            return true;
+        }
+        self.remove_unit(pos);
+        false
+    }
+
+    /// Takes the unit and its cargo out of the board, they count as lost
+    fn remove_unit(&mut self, pos: UVec2) {
+        if let Some((unit, removed)) = self.clear_unit(pos) {
+            self.stats_mut(unit.owner.0).units_lost += removed;
+        }
+    }
+
+    /// Takes the unit and its cargo out of the board without counting them as
+    /// lost, returns the unit and how many units were taken out
+    fn clear_unit(&mut self, pos: UVec2) -> Option<(Unit, u32)> {
+        let unit = self.units.remove(&pos)?;
+        let cargo = self.cargo.remove(&pos).unwrap_or_default();
+        self.moved.remove(&pos);
+        self.captures.remove(&pos);
+        Some((unit, 1 + cargo.len() as u32))
+    }
+
+    /// Points left to capture the building in the position, if a capture started
+    pub fn capture_progress(&self, pos: &UVec2) -> Option<CapturePoints> {
+        self.captures.get(pos).and_then(Capturable::pending)
+    }
+
+    /// The unit in the position takes as many capture points from the building under
+    /// it as hit points it has, when none are left the building is its owner's
+    pub fn capture_building(&mut self, pos: UVec2) -> Option<CaptureOutcome> {
+        let unit = self.units.get(&pos)?;
+        let building = self.buildings.get_mut(&pos)?;
+        if !unit.unit_type.can_capture() || building.owner == unit.owner {
+            return None;
+        }
+        let points = self.rules.building(building.build_type).capture_points;
+        let capture = self
+            .captures
+            .entry(pos)
+            .or_insert_with(|| Capturable::new(points));
+        if !capture.capture(unit.owner.0, hit_points(unit.health) as CapturePoints) {
+            return capture.pending().map(CaptureOutcome::InProgress);
+        }
+        self.captures.remove(&pos);
+        let (previous, captor) = (building.owner, unit.owner);
+        building.owner = captor;
+        self.stats_mut(captor.0).buildings_captured += 1;
+        Some(CaptureOutcome::Captured(previous))
+    }
+
+    /// Color of the player in the map, None for neutral buildings
+    pub fn player_color(&self, owner: Owner) -> Option<PlayerColor> {
+        self.map
+            .players
+            .iter()
+            .find(|player| player.id == owner.0)
+            .map(|player| player.color)
+    }
+
+    /// Tint of the sprites of the owner, neutral buildings keep their colors
+    pub fn owner_tint(&self, owner: Owner) -> Color {
+        self.player_color(owner)
+            .map(|color| color.tint())
+            .unwrap_or(Color::WHITE)
+    }
+
+    /// Damages the terrain in the position, returns the new terrain if it changed
+    pub fn damage_terrain(&mut self, pos: UVec2, damage: u8) -> Option<Terrain> {
+        let cell = self.map.cells.get_mut(pos.x as usize, pos.y as usize)?;
+        let terrain = cell.terrain.damage(damage);
+        if terrain == cell.terrain {
+            return None;
+        }
+        cell.terrain = terrain;
+        self.layers = build_layers(&self.map);
+        Some(terrain)
+    }
+
+    fn spawn_tiles(
+        &self,
+        parent: &mut ChildSpawnerCommands,
+        auto_tiler: &AutoTiler<TileTerrain, UVec2>,
+        sprites: &TerrainSprites,
+        board_position: BoardPos,
+    ) {
+        let helper = TileHelper::new(TERRAIN_SHEET_SIZE);
+        let layers = self.layers_len();
+        for idx in 0..layers {
+            if let Some(tile_coords) = self.get_tile(auto_tiler, idx, board_position.0) {
+                parent.spawn((
+                    TerrainTile,
+                    board_position.clone(),
+                    Sprite::from_atlas_image(
+                        sprites.texture.clone(),
+                        TextureAtlas {
+                            layout: sprites.layout.clone(),
+                            index: helper.index(tile_coords),
+                        },
+                    ),
+                    Transform::from_translation(
+                        board_position.get_screen_pos(idx as i32 - layers as i32),
+                    ),
+                ));
+            }
+        }
+    }
+
     pub fn get(&self, pos: &UVec2) -> Option<&Terrain> {
         let x = pos.x as usize;
         let y = pos.y as usize;
@@ -156,27 +702,38 @@ impl Board {
         mut commands: Commands,
         assets: Res<AssetServer>,
         maps: Res<Assets<Map>>,
+        rules: Res<Assets<Rules>>,
         auto_tiler: Res<Tiler>,
         mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
     ) {
-        let helper = TileHelper::new(uvec2(68, 45));
-        let unit_helper = TileHelper::new(uvec2(5, 8));
+        let helper = TileHelper::new(TERRAIN_SHEET_SIZE);
         let map_handler = FileAssets::MapTestAbwm.load::<Map>(&assets);
         let map = maps.get(&map_handler);
         let Some(map) = map else {
             bevy::log::error!("Map not correctly loaded");
             return;
         };
-        let board = Board::new(map.clone());
+        let rules = rules
+            .get(&FileAssets::DefaultRulesToml.load::<Rules>(&assets))
+            .cloned()
+            .unwrap_or_else(|| {
+                bevy::log::error!("Rules not correctly loaded, using the built-in ones");
+                Rules::builtin().clone()
+            });
+        let board = Board::with_rules(map.clone(), rules);
 
         let texture_handle = FileAssets::ImagesGameTerrainPng.load(&assets);
         let texture_atlas = helper.atlas_layout(UVec2::splat(32));
         let texture_atlas_handle = texture_atlases.add(texture_atlas);
         let auto_tiler: &AutoTiler<TileTerrain, UVec2> = &auto_tiler.0;
-        let layers = board.layers.length();
-        let unit_handle = FileAssets::ImagesGameUnitsInfantryPng.load(&assets);
-        let unit_texture_atlas = unit_helper.atlas_layout(UVec2::splat(32));
-        let unit_texture_atlas_handle = texture_atlases.add(unit_texture_atlas);
+        let sprites = TerrainSprites {
+            texture: texture_handle.clone(),
+            layout: texture_atlas_handle.clone(),
+        };
+        let unit_sprites: HashMap<UnitType, Sprite> = UnitType::ALL
+            .into_iter()
+            .map(|unit_type| (unit_type, unit_sprite(unit_type, &assets, &mut texture_atlases)))
+            .collect();
 
         commands
             .spawn((Transform::IDENTITY, Visibility::Inherited, MainBoard))
@@ -184,37 +741,13 @@ impl Board {
                 for pos in board.map.cells.keys() {
                     let cell_info = &board.map.cells[pos];
                     let board_position = BoardPos::from(pos);
-                    for (idx, layer) in board.layers.iter().enumerate() {
-                        if let Some(tile_coords) = auto_tiler.get_tile::<UVec2, Direction>(
-                            &*layer,
-                            (pos.0 as u32, pos.1 as u32).into(),
-                        ) {
-                            parent.spawn((
-                                Sprite::from_atlas_image(
-                                    texture_handle.clone(),
-                                    TextureAtlas {
-                                        layout: texture_atlas_handle.clone(),
-                                        index: helper.index(tile_coords),
-                                    },
-                                ),
-                                Transform::from_translation(
-                                    board_position.get_screen_pos(idx as i32 - layers as i32),
-                                ),
-                            ));
-                        }
-                    }
+                    board.spawn_tiles(parent, auto_tiler, &sprites, board_position.clone());
                     if let Some(unit) = cell_info.unit {
                         bevy::log::info!("We have units! {:?}", unit);
                         parent.spawn((
                             UnitComponent,
                             board_position.clone(),
-                            Sprite::from_atlas_image(
-                                unit_handle.clone(),
-                                TextureAtlas {
-                                    layout: unit_texture_atlas_handle.clone(),
-                                    index: 0,
-                                },
-                            ),
+                            unit_sprites[&unit.unit_type].clone(),
                             Transform::from_translation(board_position.get_screen_pos(1)),
                         ));
                     }
@@ -223,22 +756,122 @@ impl Board {
                         parent.spawn((
                             BuildingCompoent,
                             board_position.clone(),
-                            Sprite::from_atlas_image(
-                                texture_handle.clone(),
-                                TextureAtlas {
-                                    layout: texture_atlas_handle.clone(),
-                                    index: helper.index(uvec2(0, 37)),
-                                },
-                            ),
+                            building.owner,
+                            board.income(&building),
+                            Sprite {
+                                color: board.owner_tint(building.owner),
+                                ..Sprite::from_atlas_image(
+                                    texture_handle.clone(),
+                                    TextureAtlas {
+                                        layout: texture_atlas_handle.clone(),
+                                        index: helper.index(building.build_type.tile()),
+                                    },
+                                )
+                            },
                             Transform::from_translation(board_position.get_screen_pos(0)),
+                            children![(
+                                Sprite {
+                                    color: board.owner_tint(building.owner),
+                                    ..Sprite::from_atlas_image(
+                                        texture_handle.clone(),
+                                        TextureAtlas {
+                                            layout: texture_atlas_handle.clone(),
+                                            index: helper.index(building.build_type.top_tile()),
+                                        },
+                                    )
+                                },
+                                Transform::from_xyz(0., 32., 0.),
+                            )],
                         ));
                     }
                 }
             });
         commands.insert_resource(board);
+        commands.insert_resource(sprites);
     }
 }
 
+/// Draws again the tiles of the changed cells and their neighbors, as the auto
+/// tiler depends on them
+pub fn refresh_terrain(
+    mut commands: Commands,
+    mut changes: MessageReader<TerrainChanged>,
+    board: Res<Board>,
+    auto_tiler: Res<Tiler>,
+    sprites: Option<Res<TerrainSprites>>,
+    tiles: Query<(Entity, &BoardPos), With<TerrainTile>>,
+    main_board: Query<Entity, With<MainBoard>>,
+) {
+    let mut positions = HashSet::new();
+    for TerrainChanged(pos) in changes.read() {
+        positions.insert(*pos);
+        positions.extend(Direction::ALL.iter().filter_map(|dir| dir.move_point(pos)));
+    }
+    let (Some(sprites), Ok(main_board)) = (sprites, main_board.single()) else {
+        return;
+    };
+    let (width, height) = board.get_size();
+    positions.retain(|pos| (pos.x as usize) < width && (pos.y as usize) < height);
+    if positions.is_empty() {
+        return;
+    }
+
+    for (entity, pos) in tiles.iter() {
+        if positions.contains(&pos.0) {
+            commands.entity(entity).despawn();
+        }
+    }
+    commands.entity(main_board).with_children(|parent| {
+        for pos in positions {
+            board.spawn_tiles(parent, &auto_tiler.0, &sprites, BoardPos(pos));
+        }
+    });
+}
+
+/// First frame of the sprite sheet of the unit type
+pub fn unit_sprite(
+    unit_type: UnitType,
+    assets: &AssetServer,
+    texture_atlases: &mut Assets<TextureAtlasLayout>,
+) -> Sprite {
+    let layout = TileHelper::new(unit_type.sheet_size()).atlas_layout(UVec2::splat(32));
+    Sprite::from_atlas_image(
+        unit_type.sprite().load(assets),
+        TextureAtlas {
+            layout: texture_atlases.add(layout),
+            index: 0,
+        },
+    )
+}
+
+/// Gives the captured buildings the color of their new owner
+pub fn refresh_buildings(
+    mut captures: MessageReader<BuildingCaptured>,
+    board: Res<Board>,
+    mut buildings: Query<(&BoardPos, &mut Owner, &mut Sprite, &Children), With<BuildingCompoent>>,
+    mut tops: Query<&mut Sprite, Without<BuildingCompoent>>,
+) {
+    for BuildingCaptured(pos) in captures.read() {
+        let Some(building) = board.buildings.get(pos) else {
+            continue;
+        };
+        let tint = board.owner_tint(building.owner);
+        for (_, mut owner, mut sprite, children) in
+            buildings.iter_mut().filter(|(position, ..)| position.0 == *pos)
+        {
+            *owner = building.owner;
+            sprite.color = tint;
+            for child in children.iter() {
+                if let Ok(mut top) = tops.get_mut(child) {
+                    top.color = tint;
+                }
+            }
+        }
+    }
+}
+
+const TERRAIN_SHEET_SIZE: UVec2 = uvec2(68, 45);
+
 struct TileHelper {
     size: UVec2,
 }
@@ -276,6 +909,7 @@ pub fn center_camera(mut camera: Query<&mut Transform, With<Camera>>, board: Res
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::board::{BuildingType, terrain::build_auto_tiler, test_utils::make_board};
 
     #[test]
     fn test_rotate() {
@@ -285,4 +919,274 @@ mod tests {
         assert_eq!(Direction::West.rotate_45(12), Direction::East);
         assert_eq!(Direction::West.rotate_45(1), Direction::NorthWest);
     }
+
+    #[test]
+    fn test_bridge_orientation() {
+        let auto_tiler = build_auto_tiler();
+        let horizontal = make_board(
+            "version=1
+            width=3
+            height=1
+            terrain = [[\"r\", \"b\", \"r\"]]",
+        );
+        assert_eq!(
+            horizontal.get_tile(&auto_tiler, 2, uvec2(1, 0)),
+            Some(uvec2(14, 3))
+        );
+        assert!(horizontal.get_tile(&auto_tiler, 0, uvec2(1, 0)).is_some());
+
+        let vertical = make_board(
+            "version=1
+            width=3
+            height=1
+            terrain = [[\"s\", \"b\", \"s\"]]",
+        );
+        assert_eq!(
+            vertical.get_tile(&auto_tiler, 2, uvec2(1, 0)),
+            Some(uvec2(13, 2))
+        );
+    }
+
+    #[test]
+    fn test_destroy_breakable_wall() {
+        let auto_tiler = build_auto_tiler();
+        let mut board = make_board(
+            "version=1
+            width=3
+            height=1
+            terrain = [[\"w\", \"bw:50\", \"w\"]]",
+        );
+        assert_eq!(
+            board.get_tile(&auto_tiler, 2, uvec2(1, 0)),
+            Some(uvec2(4, 19))
+        );
+        assert_eq!(board.damage_terrain(uvec2(0, 0), 50), None);
+        assert_eq!(board.damage_terrain(uvec2(1, 0), 50), Some(Terrain::Plane));
+        assert_eq!(board.get(&uvec2(1, 0)), Some(&Terrain::Plane));
+        assert_eq!(board.get_tile(&auto_tiler, 2, uvec2(1, 0)), None);
+    }
+
+    #[test]
+    fn test_move_unit() {
+        let mut board = make_board(
+            "version=1
+            width=3
+            height=1
+            terrain = [[\"p\", \"p\", \"p\"]]
+            [units]
+            1x1 = {type=\"infantry\", owner=1}
+            3x1 = {type=\"infantry\", owner=1}",
+        );
+        let unit = board.units[&uvec2(0, 0)];
+
+        assert!(!board.move_unit(uvec2(0, 0), uvec2(2, 0)), "The cell is taken");
+        assert!(!board.move_unit(uvec2(1, 0), uvec2(0, 0)), "There is no unit");
+        assert!(board.move_unit(uvec2(0, 0), uvec2(1, 0)));
+        assert_eq!(board.units.get(&uvec2(1, 0)), Some(&unit));
+        assert!(!board.units.contains_key(&uvec2(0, 0)));
+        assert!(board.has_moved(&uvec2(1, 0)));
+        assert!(!board.move_unit(uvec2(1, 0), uvec2(0, 0)), "It already moved");
+
+        assert!(board.undo_move(uvec2(0, 0), uvec2(1, 0)));
+        assert_eq!(board.units.get(&uvec2(0, 0)), Some(&unit));
+        assert!(!board.has_moved(&uvec2(0, 0)));
+        assert!(!board.undo_move(uvec2(0, 0), uvec2(1, 0)));
+
+        assert!(board.move_unit(uvec2(0, 0), uvec2(1, 0)));
+        board.start_turn();
+        assert!(!board.has_moved(&uvec2(1, 0)), "A new turn started");
+        assert!(!board.undo_move(uvec2(0, 0), uvec2(1, 0)));
+    }
+
+    #[test]
+    fn test_load_and_join() {
+        let mut board = make_board(
+            "version=1
+            width=4
+            height=1
+            terrain = [[\"p\", \"p\", \"p\", \"p\"]]
+            [units]
+            1x1 = {type=\"infantry\", owner=1, life=70}
+            2x1 = {type=\"apc\", owner=1}
+            3x1 = {type=\"infantry\", owner=1}
+            4x1 = {type=\"infantry\", owner=1, life=60}",
+        );
+        let passenger = board.units[&uvec2(2, 0)];
+
+        assert!(!board.join_units(uvec2(2, 0), uvec2(1, 0)));
+        assert!(board.load_unit(uvec2(2, 0), uvec2(1, 0)));
+        assert_eq!(board.cargo(&uvec2(1, 0)), &[passenger]);
+        assert!(!board.load_unit(uvec2(0, 0), uvec2(1, 0)), "The APC is full");
+
+        assert!(board.move_unit(uvec2(1, 0), uvec2(2, 0)));
+        assert_eq!(board.cargo(&uvec2(2, 0)), &[passenger]);
+        assert!(board.cargo(&uvec2(1, 0)).is_empty());
+
+        assert!(board.join_units(uvec2(0, 0), uvec2(3, 0)));
+        assert_eq!(board.units[&uvec2(3, 0)].health, Life::new());
+        assert!(!board.units.contains_key(&uvec2(0, 0)));
+        assert!(board.has_moved(&uvec2(3, 0)));
+    }
+
+    #[test]
+    fn test_capture_building() {
+        let mut board = make_board(
+            "version=1
+            width=4
+            height=1
+            terrain = [[\"p\", \"p\", \"p\", \"p\"]]
+            [buildings]
+            1x1 = {type=\"city\", owner=2}
+            2x1 = {type=\"town\"}
+            3x1 = {type=\"city\", owner=2}
+            [units]
+            1x1 = {type=\"infantry\", owner=1}
+            2x1 = {type=\"mech\", owner=1, life=45}
+            3x1 = {type=\"tank\", owner=1}",
+        );
+
+        assert_eq!(board.capture_building(uvec2(2, 0)), None, "Tanks can not capture");
+        assert_eq!(board.capture_progress(&uvec2(0, 0)), None);
+        assert_eq!(
+            board.capture_building(uvec2(0, 0)),
+            Some(CaptureOutcome::InProgress(10))
+        );
+        assert_eq!(board.capture_progress(&uvec2(0, 0)), Some(10));
+        assert_eq!(
+            board.capture_building(uvec2(0, 0)),
+            Some(CaptureOutcome::Captured(Owner(2)))
+        );
+        assert_eq!(board.buildings[&uvec2(0, 0)].owner, Owner(1));
+        assert_eq!(board.capture_progress(&uvec2(0, 0)), None);
+        assert_eq!(board.capture_building(uvec2(0, 0)), None, "It is already ours");
+
+        // Damaged units capture slower
+        assert_eq!(
+            board.capture_building(uvec2(1, 0)),
+            Some(CaptureOutcome::InProgress(5))
+        );
+        assert!(board.move_unit(uvec2(1, 0), uvec2(1, 0)));
+        assert_eq!(board.capture_progress(&uvec2(1, 0)), Some(5), "It did not leave");
+    }
+
+    #[test]
+    fn test_upkeep() {
+        let mut board = make_board(
+            "version=2
+            width=4
+            height=1
+            terrain = [[\"p\", \"p\", \"p\", \"p\"]]
+            [players]
+            1 = {funds=0}
+            2 = {funds=10}
+            [buildings]
+            1x1 = {type=\"city\", owner=1, income=150}
+            2x1 = {type=\"city\", owner=1, income=150}
+            4x1 = {type=\"city\", owner=2, income=150}
+            [units]
+            1x1 = {type=\"infantry\", owner=1, life=50}
+            2x1 = {type=\"tank\", owner=1, life=50}
+            3x1 = {type=\"mech\", owner=1, life=50}
+            4x1 = {type=\"infantry\", owner=2, life=50}",
+        );
+        for unit in board.units.values_mut() {
+            unit.ammo_used = 1;
+            unit.fuel_used = 10;
+        }
+
+        assert_eq!(
+            board.upkeep(1),
+            Upkeep {
+                income: 300,
+                // The money left only pays one point of life of the tank
+                repairs: vec![(uvec2(0, 0), 20), (uvec2(1, 0), 1)],
+                repair_cost: 270,
+                resupplied: vec![uvec2(0, 0), uvec2(1, 0)],
+            }
+        );
+        assert_eq!(board.funds(1), 30);
+        assert_eq!(board.funds(2), 10);
+
+        assert_eq!(board.units[&uvec2(0, 0)].health, Life(70));
+        assert_eq!(board.units[&uvec2(1, 0)].health, Life(51));
+        assert_eq!(board.units[&uvec2(2, 0)].health, Life(50), "It is not on a building");
+        assert_eq!(board.units[&uvec2(3, 0)].health, Life(50), "It is not its turn");
+        assert_eq!(board.units[&uvec2(1, 0)].fuel_used, 0);
+        assert_eq!(board.units[&uvec2(1, 0)].ammo_used, 0);
+        assert_eq!(board.units[&uvec2(2, 0)].fuel_used, 10);
+
+        assert!(!board.spend(1, 31));
+        assert!(board.spend(1, 30));
+        assert_eq!(board.funds(1), 0);
+
+        let mut building = board.buildings[&uvec2(3, 0)];
+        building.income = None;
+        assert_eq!(
+            board.income(&building),
+            board.rules().building(BuildingType::City).income,
+            "The rules decide the income the map doesn't set"
+        );
+    }
+
+    #[test]
+    fn test_build_unit() {
+        let mut board = make_board(
+            "version=2
+            width=4
+            height=1
+            terrain = [[\"p\", \"p\", \"p\", \"p\"]]
+            [players]
+            1 = {funds=8000}
+            2 = {funds=8000}
+            [buildings]
+            1x1 = {type=\"factory\", owner=1}
+            2x1 = {type=\"factory\", owner=1}
+            3x1 = {type=\"city\", owner=1}
+            [units]
+            2x1 = {type=\"infantry\", owner=1}",
+        );
+
+        assert!(board.buildable_units(&uvec2(0, 0)).contains(&UnitType::Tank));
+        assert!(!board.buildable_units(&uvec2(0, 0)).contains(&UnitType::Fighter));
+        assert!(board.buildable_units(&uvec2(2, 0)).is_empty());
+        assert!(!board.can_build(&uvec2(1, 0), UnitType::Infantry, 1), "It is taken");
+        assert!(!board.build_unit(uvec2(0, 0), UnitType::Tank, 2), "It is not theirs");
+
+        assert!(board.build_unit(uvec2(0, 0), UnitType::Tank, 1));
+        assert_eq!(board.funds(1), 1000);
+        let tank = board.units[&uvec2(0, 0)];
+        assert_eq!((tank.owner, tank.unit_type), (Owner(1), UnitType::Tank));
+        assert!(board.has_moved(&uvec2(0, 0)), "It waits until the next turn");
+        assert!(!board.can_build(&uvec2(0, 0), UnitType::Infantry, 1));
+
+        board.units.remove(&uvec2(0, 0));
+        assert!(!board.can_build(&uvec2(0, 0), UnitType::Reccon, 1), "Not enough money");
+        assert!(board.can_build(&uvec2(0, 0), UnitType::Infantry, 1));
+    }
+
+    #[test]
+    fn test_capture_reset() {
+        let mut board = make_board(
+            "version=1
+            width=3
+            height=1
+            terrain = [[\"p\", \"p\", \"p\"]]
+            [buildings]
+            1x1 = {type=\"city\", owner=2}
+            [units]
+            1x1 = {type=\"infantry\", owner=1}
+            3x1 = {type=\"tank\", owner=2}",
+        );
+
+        board.capture_building(uvec2(0, 0));
+        assert!(board.move_unit(uvec2(0, 0), uvec2(1, 0)));
+        assert_eq!(board.capture_progress(&uvec2(0, 0)), None, "The capturer left");
+        assert!(board.undo_move(uvec2(0, 0), uvec2(1, 0)));
+        assert_eq!(board.capture_progress(&uvec2(0, 0)), Some(10), "The move was undone");
+
+        assert!(board.hit_unit(uvec2(0, 0), 50));
+        assert_eq!(board.capture_progress(&uvec2(0, 0)), Some(10));
+        assert!(!board.hit_unit(uvec2(0, 0), 50));
+        assert_eq!(board.capture_progress(&uvec2(0, 0)), None, "The capturer died");
+    }
 }
@@ -0,0 +1,678 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    board::{
+        board::Board,
+        map::{AttackRange, Terrain, Unit, UnitClass, UnitType},
+    },
+    interactive::Life,
+};
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Weapon {
+    /// Main weapon, it needs ammo
+    Primary,
+    /// Machine guns and the like, always available
+    Secondary,
+}
+
+impl Weapon {
+    pub const ALL: [Weapon; 2] = [Self::Primary, Self::Secondary];
+
+    /// Name used in the rules files
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Primary => "primary",
+            Self::Secondary => "secondary",
+        }
+    }
+}
+
+/// Source of the luck of the attacks, the same seed plays the same battles
+#[derive(Resource)]
+pub struct CombatRng(StdRng);
+
+impl CombatRng {
+    pub fn new(seed: u64) -> Self {
+        Self(StdRng::seed_from_u64(seed))
+    }
+
+    /// Extra damage added to an attack, from 0 to 9
+    pub fn luck(&mut self) -> u32 {
+        self.0.random_range(0..10)
+    }
+}
+
+/// Lowest and highest damage, in life points
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DamageRange {
+    pub min: u8,
+    pub max: u8,
+}
+
+/// Expected result of an attack before confirming it
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DamageForecast {
+    pub damage: DamageRange,
+    /// None when the defender can not fire back
+    pub counter: Option<DamageRange>,
+}
+
+/// Seeds the luck of the battles with the time the board is shown
+pub fn seed_combat_rng(mut commands: Commands) {
+    let seed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    commands.insert_resource(CombatRng::new(seed));
+}
+
+/// Outcome of an attack, the damage is in life points
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CombatResult {
+    pub damage: u8,
+    pub defender_destroyed: bool,
+    /// Damage of the counterattack, None when the defender could not fire back
+    pub counter_damage: Option<u8>,
+    pub attacker_destroyed: bool,
+}
+
+/// Manhattan distance between two cells
+pub fn distance(from: UVec2, to: UVec2) -> u32 {
+    from.x.abs_diff(to.x) + from.y.abs_diff(to.y)
+}
+
+/// Hit points shown to the player, from 1 to 10 while the unit is alive
+pub fn hit_points(life: Life) -> u32 {
+    (life.0 as u32).div_ceil(10)
+}
+
+/// Damage dealt by an attack: the base damage plus the luck, scaled by the hit
+/// points of the attacker and reduced by the defense stars times the hit points
+/// of the defender
+pub fn damage(base: u32, luck: u32, attacker: Life, defense: u8, defender: Life) -> u8 {
+    let reduction = (defense as u32 * hit_points(defender)).min(100);
+    let damage = (base + luck) * hit_points(attacker) * (100 - reduction) / 1000;
+    damage.min(u8::MAX as u32) as u8
+}
+
+impl Board {
+    /// Weapon used against the defender and its base damage, the primary weapon
+    /// is preferred when the unit has ammo for it
+    pub fn weapon_against(&self, attacker: &Unit, defender: UnitType) -> Option<(Weapon, u32)> {
+        self.choose_weapon(attacker, |weapon| {
+            self.rules()
+                .base_damage(attacker.unit_type, weapon, defender)
+        })
+    }
+
+    /// Weapon used against breakable walls and its base damage
+    pub fn weapon_against_wall(&self, attacker: &Unit) -> Option<(Weapon, u32)> {
+        self.choose_weapon(attacker, |weapon| {
+            self.rules().wall_damage(attacker.unit_type, weapon)
+        })
+    }
+
+    fn choose_weapon(
+        &self,
+        attacker: &Unit,
+        base_damage: impl Fn(Weapon) -> Option<u32>,
+    ) -> Option<(Weapon, u32)> {
+        let has_ammo = self.ammo_left(attacker) > 0;
+        Weapon::ALL
+            .into_iter()
+            .filter(|weapon| has_ammo || *weapon != Weapon::Primary)
+            .find_map(|weapon| base_damage(weapon).map(|base| (weapon, base)))
+    }
+
+    /// Defense stars of the unit in the position, air units don't get any
+    pub fn defense_for(&self, unit: &Unit, pos: &UVec2) -> u8 {
+        if self.rules().unit(unit.unit_type).class() == UnitClass::Air {
+            return 0;
+        }
+        match self.buildings.get(pos) {
+            Some(building) => self.rules().building(building.build_type).defense,
+            None => self
+                .get(pos)
+                .map(|terrain| self.rules().defense(terrain))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Cells of the board at a distance in the range from the position
+    pub fn cells_in_range(&self, pos: UVec2, range: AttackRange) -> Vec<UVec2> {
+        let (width, height) = self.get_size();
+        let mut cells = vec![];
+        for y in pos.y.saturating_sub(range.max)..=(pos.y + range.max).min(height as u32 - 1) {
+            for x in pos.x.saturating_sub(range.max)..=(pos.x + range.max).min(width as u32 - 1) {
+                if range.contains(distance(pos, uvec2(x, y))) {
+                    cells.push(uvec2(x, y));
+                }
+            }
+        }
+        cells
+    }
+
+    /// Cells the unit in the position threatens this turn, direct units can move
+    /// before firing
+    pub fn threat_range(&self, pos: UVec2) -> Vec<UVec2> {
+        let Some(unit) = self.units.get(&pos) else {
+            return vec![];
+        };
+        let Some(range) = self.rules().unit(unit.unit_type).attack_range else {
+            return vec![];
+        };
+        if range.is_indirect() {
+            return self.cells_in_range(pos, range);
+        }
+        let cells: HashSet<UVec2> = unit
+            .get_movements(pos, self)
+            .iter()
+            .filter(|mov| mov.can_stop)
+            .flat_map(|mov| self.cells_in_range(mov.position, range))
+            .collect();
+        let mut cells: Vec<UVec2> = cells.into_iter().collect();
+        cells.sort_by_key(|cell| (cell.y, cell.x));
+        cells
+    }
+
+    /// Enemies and breakable walls the unit can attack from the position, indirect
+    /// units can not attack after moving
+    pub fn attack_targets(&self, unit: &Unit, pos: UVec2, moved: bool) -> Vec<UVec2> {
+        self.attack_targets_seeing(unit, pos, moved, |_| true)
+    }
+
+    /// Same as [`Board::attack_targets`] but only the enemies in visible cells can
+    /// be targeted
+    pub fn attack_targets_seeing(
+        &self,
+        unit: &Unit,
+        pos: UVec2,
+        moved: bool,
+        is_visible: impl Fn(&UVec2) -> bool,
+    ) -> Vec<UVec2> {
+        let Some(range) = self.rules().unit(unit.unit_type).attack_range else {
+            return vec![];
+        };
+        if moved && range.is_indirect() {
+            return vec![];
+        }
+        let mut targets: Vec<UVec2> = self
+            .units
+            .iter()
+            .filter(|(target, other)| {
+                other.owner != unit.owner
+                    && range.contains(distance(pos, **target))
+                    && self.weapon_against(unit, other.unit_type).is_some()
+                    && is_visible(target)
+            })
+            .map(|(target, _)| *target)
+            .collect();
+        if self.weapon_against_wall(unit).is_some() {
+            targets.extend(self.cells_in_range(pos, range).into_iter().filter(|cell| {
+                matches!(self.get(cell), Some(Terrain::BreakableWall(_)))
+                    && !self.units.contains_key(cell)
+            }));
+        }
+        targets.sort_by_key(|target| (target.y, target.x));
+        targets
+    }
+
+    /// Damage dealt and received in an attack without applying it, the luck is
+    /// asked first for the attack and then for the counterattack. Walls have no
+    /// defense and never fire back.
+    fn exchange(
+        &self,
+        attacker: UVec2,
+        defender: UVec2,
+        mut luck: impl FnMut() -> u32,
+    ) -> Option<(u8, Option<u8>)> {
+        let attacking = self.units.get(&attacker)?;
+        let Some(defending) = self.units.get(&defender) else {
+            let Some(Terrain::BreakableWall(wall)) = self.get(&defender) else {
+                return None;
+            };
+            let (_, base) = self.weapon_against_wall(attacking)?;
+            return Some((damage(base, luck(), attacking.health, 0, *wall), None));
+        };
+        let (_, base) = self.weapon_against(attacking, defending.unit_type)?;
+        let dealt = damage(
+            base,
+            luck(),
+            attacking.health,
+            self.defense_for(defending, &defender),
+            defending.health,
+        );
+        let remaining = Life(defending.health.0.saturating_sub(dealt));
+
+        let can_counter = remaining.0 > 0
+            && distance(attacker, defender) == 1
+            && self
+                .rules()
+                .unit(defending.unit_type)
+                .attack_range
+                .is_some_and(|range| !range.is_indirect());
+        let counter = self
+            .weapon_against(defending, attacking.unit_type)
+            .filter(|_| can_counter)
+            .map(|(_, base)| {
+                damage(
+                    base,
+                    luck(),
+                    remaining,
+                    self.defense_for(attacking, &attacker),
+                    attacking.health,
+                )
+            });
+        Some((dealt, counter))
+    }
+
+    /// Damage the attack would deal and receive with the worst and the best luck
+    pub fn forecast(&self, attacker: UVec2, defender: UVec2) -> Option<DamageForecast> {
+        // The more damage dealt the weaker the counterattack
+        let mut rolls = [0, 9].into_iter();
+        let (min_damage, max_counter) =
+            self.exchange(attacker, defender, || rolls.next().unwrap_or_default())?;
+        let mut rolls = [9, 0].into_iter();
+        let (max_damage, min_counter) =
+            self.exchange(attacker, defender, || rolls.next().unwrap_or_default())?;
+        Some(DamageForecast {
+            damage: DamageRange {
+                min: min_damage,
+                max: max_damage,
+            },
+            counter: max_counter.map(|max| DamageRange {
+                min: min_counter.unwrap_or_default(),
+                max,
+            }),
+        })
+    }
+
+    /// The unit in `attacker` fires at the one in `defender`, which fires back when
+    /// it survives, is next to the attacker and has a weapon against it. Destroyed
+    /// units are removed from the board and destroyed walls turn into plain.
+    pub fn attack(
+        &mut self,
+        attacker: UVec2,
+        defender: UVec2,
+        luck: impl FnMut() -> u32,
+    ) -> Option<CombatResult> {
+        let attacking = *self.units.get(&attacker)?;
+        let (dealt, counter) = self.exchange(attacker, defender, luck)?;
+        let Some(defending) = self.units.get(&defender).copied() else {
+            self.spend_ammo(attacker, self.weapon_against_wall(&attacking));
+            let defender_destroyed = self.damage_terrain(defender, dealt) == Some(Terrain::Plane);
+            return Some(CombatResult {
+                damage: dealt,
+                defender_destroyed,
+                counter_damage: None,
+                attacker_destroyed: false,
+            });
+        };
+        self.spend_ammo(
+            attacker,
+            self.weapon_against(&attacking, defending.unit_type),
+        );
+        if counter.is_some() {
+            self.spend_ammo(
+                defender,
+                self.weapon_against(&defending, attacking.unit_type),
+            );
+        }
+        let attacker_owner = attacking.owner.0;
+        let defender_owner = defending.owner.0;
+        let defender_destroyed = !self.hit_unit(defender, dealt);
+        let attacker_destroyed = counter.is_some_and(|counter| !self.hit_unit(attacker, counter));
+        if defender_destroyed {
+            self.stats_mut(attacker_owner).units_destroyed += 1;
+        }
+        if attacker_destroyed {
+            self.stats_mut(defender_owner).units_destroyed += 1;
+        }
+
+        Some(CombatResult {
+            damage: dealt,
+            defender_destroyed,
+            counter_damage: counter,
+            attacker_destroyed,
+        })
+    }
+
+    /// Shots of the primary weapon use ammo
+    fn spend_ammo(&mut self, pos: UVec2, weapon: Option<(Weapon, u32)>) {
+        if let Some((Weapon::Primary, _)) = weapon
+            && let Some(unit) = self.units.get_mut(&pos)
+        {
+            unit.ammo_used += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::test_utils::{make_board, row_map};
+
+    const PLAINS: &[&str] = &["p"; 4];
+
+    #[test]
+    fn test_hit_points() {
+        assert_eq!(hit_points(Life(100)), 10);
+        assert_eq!(hit_points(Life(91)), 10);
+        assert_eq!(hit_points(Life(90)), 9);
+        assert_eq!(hit_points(Life(1)), 1);
+        assert_eq!(hit_points(Life(0)), 0);
+    }
+
+    #[test]
+    fn test_damage_formula() {
+        // Tank against tank on a plain
+        assert_eq!(damage(55, 0, Life(100), 1, Life(100)), 49);
+        assert_eq!(damage(55, 9, Life(100), 1, Life(100)), 57);
+        // Half life attackers do half the damage
+        assert_eq!(damage(55, 0, Life(50), 1, Life(100)), 24);
+        // Mountains give 4 stars, which matter less for damaged defenders
+        assert_eq!(damage(55, 0, Life(100), 4, Life(100)), 33);
+        assert_eq!(damage(55, 0, Life(100), 4, Life(20)), 50);
+        assert_eq!(damage(55, 0, Life(100), 0, Life(100)), 55);
+        assert_eq!(damage(125, 9, Life(100), 0, Life(100)), 134);
+        assert_eq!(damage(1, 0, Life(10), 4, Life(100)), 0);
+    }
+
+    #[test]
+    fn test_weapon_choice() {
+        let board = make_board(&row_map(
+            PLAINS,
+            "",
+            "1x1 = {type=\"tank\", owner=1}
+            2x1 = {type=\"infantry\", owner=1}
+            3x1 = {type=\"apc\", owner=1}",
+            "",
+        ));
+        let tank = board.units[&uvec2(0, 0)];
+        let infantry = board.units[&uvec2(1, 0)];
+        let apc = board.units[&uvec2(2, 0)];
+
+        assert_eq!(
+            board.weapon_against(&tank, UnitType::Tank),
+            Some((Weapon::Primary, 55))
+        );
+        assert_eq!(
+            board.weapon_against(&tank, UnitType::Infantry),
+            Some((Weapon::Secondary, 75))
+        );
+        assert_eq!(
+            board.weapon_against(&infantry, UnitType::Infantry),
+            Some((Weapon::Secondary, 55))
+        );
+        assert_eq!(board.weapon_against(&tank, UnitType::Fighter), None);
+        assert_eq!(board.weapon_against(&apc, UnitType::Infantry), None);
+
+        let empty = Unit {
+            ammo_used: board.rules().unit(UnitType::Tank).ammo,
+            ..tank
+        };
+        assert_eq!(
+            board
+                .weapon_against(&empty, UnitType::Tank)
+                .map(|(weapon, _)| weapon),
+            Some(Weapon::Secondary),
+            "Without ammo the secondary weapon is used"
+        );
+    }
+
+    #[test]
+    fn test_defense() {
+        let board = make_board(&row_map(
+            &["m", "p", "s", "p"],
+            "",
+            "1x1 = {type=\"infantry\", owner=1}
+            2x1 = {type=\"bcopter\", owner=1}",
+            "",
+        ));
+        let infantry = board.units[&uvec2(0, 0)];
+        let copter = board.units[&uvec2(1, 0)];
+
+        assert_eq!(board.defense_for(&infantry, &uvec2(0, 0)), 4);
+        assert_eq!(board.defense_for(&infantry, &uvec2(1, 0)), 1);
+        assert_eq!(board.defense_for(&copter, &uvec2(0, 0)), 0);
+    }
+
+    #[test]
+    fn test_attack_with_counter() {
+        let mut board = make_board(&row_map(
+            PLAINS,
+            "",
+            "1x1 = {type=\"tank\", owner=1}
+            2x1 = {type=\"tank\", owner=2}",
+            "",
+        ));
+
+        let result = board.attack(uvec2(0, 0), uvec2(1, 0), || 0).unwrap();
+        assert_eq!(
+            result,
+            CombatResult {
+                damage: 49,
+                defender_destroyed: false,
+                // 51 of life are 6 hit points: 55 * 6 * (100 - 10) / 1000
+                counter_damage: Some(29),
+                attacker_destroyed: false,
+            }
+        );
+        assert_eq!(board.units[&uvec2(1, 0)].health, Life(51));
+        assert_eq!(board.units[&uvec2(0, 0)].health, Life(71));
+        assert_eq!(board.units[&uvec2(0, 0)].ammo_used, 1);
+        assert_eq!(
+            board.units[&uvec2(1, 0)].ammo_used,
+            1,
+            "The counterattack uses ammo too"
+        );
+    }
+
+    #[test]
+    fn test_forecast() {
+        let mut board = make_board(&row_map(
+            PLAINS,
+            "",
+            "1x1 = {type=\"tank\", owner=1}
+            2x1 = {type=\"tank\", owner=2}
+            4x1 = {type=\"infantry\", owner=2, life=10}",
+            "",
+        ));
+
+        assert_eq!(
+            board.forecast(uvec2(0, 0), uvec2(1, 0)),
+            Some(DamageForecast {
+                damage: DamageRange { min: 49, max: 57 },
+                // 43 of life left are 5 hit points and 51 are 6
+                counter: Some(DamageRange { min: 24, max: 34 }),
+            })
+        );
+        assert_eq!(
+            board.forecast(uvec2(2, 0), uvec2(3, 0)),
+            None,
+            "There is no unit attacking"
+        );
+        let forecast = board.forecast(uvec2(1, 0), uvec2(0, 0)).unwrap();
+        assert_eq!(
+            board.units[&uvec2(0, 0)].health,
+            Life(100),
+            "Nothing changes"
+        );
+
+        let mut rolls = [9, 0].into_iter();
+        let result = board
+            .attack(uvec2(1, 0), uvec2(0, 0), || rolls.next().unwrap())
+            .unwrap();
+        assert_eq!(result.damage, forecast.damage.max);
+        assert_eq!(
+            result.counter_damage,
+            forecast.counter.map(|counter| counter.min)
+        );
+    }
+
+    #[test]
+    fn test_indirect_attack() {
+        let mut board = make_board(&row_map(
+            PLAINS,
+            "",
+            "1x1 = {type=\"artillery\", owner=1}
+            2x1 = {type=\"infantry\", owner=2}
+            3x1 = {type=\"tank\", owner=2}",
+            "",
+        ));
+        let artillery = board.units[&uvec2(0, 0)];
+
+        assert_eq!(
+            board.attack_targets(&artillery, uvec2(0, 0), false),
+            vec![uvec2(2, 0)]
+        );
+        assert_eq!(board.attack_targets(&artillery, uvec2(0, 0), true), vec![]);
+        let result = board.attack(uvec2(0, 0), uvec2(2, 0), || 5).unwrap();
+        assert_eq!(result.damage, 67);
+        assert_eq!(
+            result.counter_damage, None,
+            "Indirect attacks are not countered"
+        );
+    }
+
+    #[test]
+    fn test_cells_in_range() {
+        let board = make_board(&row_map(PLAINS, "", "", ""));
+
+        assert_eq!(
+            board.cells_in_range(uvec2(0, 0), AttackRange { min: 2, max: 3 }),
+            vec![uvec2(2, 0), uvec2(3, 0)]
+        );
+        assert_eq!(
+            board.cells_in_range(uvec2(1, 0), AttackRange { min: 1, max: 1 }),
+            vec![uvec2(0, 0), uvec2(2, 0)]
+        );
+    }
+
+    #[test]
+    fn test_threat_range() {
+        let board = make_board(&row_map(
+            &["p", "p", "p", "m"],
+            "",
+            "1x1 = {type=\"artillery\", owner=1}
+            4x1 = {type=\"tank\", owner=2}",
+            "",
+        ));
+
+        assert_eq!(
+            board.threat_range(uvec2(0, 0)),
+            vec![uvec2(2, 0), uvec2(3, 0)]
+        );
+        // The tank reaches every plain, from there it also covers its own mountain
+        assert_eq!(
+            board.threat_range(uvec2(3, 0)),
+            vec![uvec2(0, 0), uvec2(1, 0), uvec2(2, 0), uvec2(3, 0)]
+        );
+        assert_eq!(board.threat_range(uvec2(2, 0)), vec![]);
+    }
+
+    #[test]
+    fn test_destroy_units() {
+        let mut board = make_board(&row_map(
+            PLAINS,
+            "",
+            "1x1 = {type=\"tank\", owner=1}
+            2x1 = {type=\"infantry\", owner=2, life=30}",
+            "",
+        ));
+
+        let result = board.attack(uvec2(0, 0), uvec2(1, 0), || 0).unwrap();
+        assert!(result.defender_destroyed);
+        assert_eq!(result.counter_damage, None);
+        assert!(!board.units.contains_key(&uvec2(1, 0)));
+        assert_eq!(board.stats(1).units_destroyed, 1);
+        assert_eq!(board.stats(2).units_lost, 1);
+
+        let mut board = make_board(&row_map(
+            PLAINS,
+            "",
+            "1x1 = {type=\"infantry\", owner=1, life=5}
+            2x1 = {type=\"tank\", owner=2}",
+            "",
+        ));
+        let result = board.attack(uvec2(0, 0), uvec2(1, 0), || 0).unwrap();
+        assert_eq!(result.damage, 0);
+        assert!(result.attacker_destroyed);
+        assert!(!board.units.contains_key(&uvec2(0, 0)));
+    }
+
+    #[test]
+    fn test_attack_wall() {
+        let mut board = make_board(&row_map(
+            &["p", "bw", "p", "p"],
+            "",
+            "1x1 = {type=\"tank\", owner=1}
+            3x1 = {type=\"apc\", owner=1}",
+            "",
+        ));
+        let tank = board.units[&uvec2(0, 0)];
+        let apc = board.units[&uvec2(2, 0)];
+
+        assert_eq!(
+            board.attack_targets(&tank, uvec2(0, 0), false),
+            vec![uvec2(1, 0)]
+        );
+        assert_eq!(board.attack_targets(&apc, uvec2(2, 0), false), vec![]);
+        assert_eq!(
+            board.forecast(uvec2(0, 0), uvec2(1, 0)),
+            Some(DamageForecast {
+                damage: DamageRange { min: 55, max: 64 },
+                counter: None,
+            })
+        );
+        let result = board.attack(uvec2(0, 0), uvec2(1, 0), || 0).unwrap();
+        assert_eq!(result.damage, 55);
+        assert!(!result.defender_destroyed);
+        assert_eq!(
+            board.get(&uvec2(1, 0)),
+            Some(&Terrain::BreakableWall(Life(45)))
+        );
+
+        let result = board.attack(uvec2(0, 0), uvec2(1, 0), || 0).unwrap();
+        assert!(result.defender_destroyed);
+        assert_eq!(board.get(&uvec2(1, 0)), Some(&Terrain::Plane));
+        assert_eq!(board.attack_targets(&tank, uvec2(0, 0), false), vec![]);
+    }
+
+    #[test]
+    fn test_hidden_targets() {
+        let board = make_board(&row_map(
+            PLAINS,
+            "",
+            "1x1 = {type=\"artillery\", owner=1}
+            3x1 = {type=\"infantry\", owner=2}
+            4x1 = {type=\"infantry\", owner=2}",
+            "",
+        ));
+        let artillery = board.units[&uvec2(0, 0)];
+        let hidden = uvec2(3, 0);
+
+        assert_eq!(
+            board.attack_targets_seeing(&artillery, uvec2(0, 0), false, |pos| *pos != hidden),
+            vec![uvec2(2, 0)]
+        );
+        assert_eq!(
+            board.attack_targets_seeing(&artillery, uvec2(0, 0), false, |_| false),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_seeded_luck() {
+        let mut first = CombatRng::new(42);
+        let mut second = CombatRng::new(42);
+        let rolls: Vec<_> = (0..20).map(|_| first.luck()).collect();
+
+        assert_eq!(rolls, (0..20).map(|_| second.luck()).collect::<Vec<_>>());
+        assert!(rolls.iter().all(|luck| *luck < 10));
+    }
+}
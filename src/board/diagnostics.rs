@@ -0,0 +1,143 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Where a problem was found in a map, rows, columns and lines start at 1 like in
+/// the map files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapLocation {
+    File { line: usize, column: usize },
+    Property(String),
+    TerrainRow(usize),
+    Terrain { row: usize, column: usize },
+    Building(String),
+    Unit(String),
+    Player(String),
+    Cell { x: usize, y: usize },
+}
+
+impl MapLocation {
+    /// Location of a cell of the map, from its (col, row) index
+    pub fn cell(pos: (usize, usize)) -> Self {
+        Self::Cell {
+            x: pos.0 + 1,
+            y: pos.1 + 1,
+        }
+    }
+
+    /// Converts a byte offset of the content into a line and column
+    pub fn from_offset(content: &str, offset: usize) -> Self {
+        let offset = offset.min(content.len());
+        let before = content.as_bytes()[..offset].split(|byte| *byte == b'\n');
+        let mut line = 0;
+        let mut column = 0;
+        for text in before {
+            line += 1;
+            column = String::from_utf8_lossy(text).chars().count() + 1;
+        }
+        Self::File { line, column }
+    }
+}
+
+impl fmt::Display for MapLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File { line, column } => write!(f, "line {line}, column {column}"),
+            Self::Property(name) => write!(f, "property {name}"),
+            Self::TerrainRow(row) => write!(f, "terrain row {row}"),
+            Self::Terrain { row, column } => write!(f, "terrain row {row}, column {column}"),
+            Self::Building(key) => write!(f, "building {key}"),
+            Self::Unit(key) => write!(f, "unit {key}"),
+            Self::Player(key) => write!(f, "player {key}"),
+            Self::Cell { x, y } => write!(f, "cell {x}x{y}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapDiagnostic {
+    pub severity: Severity,
+    pub location: Option<MapLocation>,
+    pub message: String,
+}
+
+impl fmt::Display for MapDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{}: {location}: {}", self.severity, self.message),
+            None => write!(f, "{}: {}", self.severity, self.message),
+        }
+    }
+}
+
+/// Problems collected while reading a map, so all of them can be reported at once
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MapDiagnostics(pub Vec<MapDiagnostic>);
+
+impl MapDiagnostics {
+    pub fn error(&mut self, location: Option<MapLocation>, message: impl Into<String>) {
+        self.0.push(MapDiagnostic {
+            severity: Severity::Error,
+            location,
+            message: message.into(),
+        });
+    }
+
+    pub fn warning(&mut self, location: Option<MapLocation>, message: impl Into<String>) {
+        self.0.push(MapDiagnostic {
+            severity: Severity::Warning,
+            location,
+            message: message.into(),
+        });
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.0
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+}
+
+impl fmt::Display for MapDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in self.0.iter() {
+            writeln!(f, "{diagnostic}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_location_from_offset() {
+        let content = "version=1\nwidth=1\nheight=x";
+        assert_eq!(
+            MapLocation::from_offset(content, 0),
+            MapLocation::File { line: 1, column: 1 }
+        );
+        assert_eq!(
+            MapLocation::from_offset(content, 17),
+            MapLocation::File { line: 2, column: 8 }
+        );
+        assert_eq!(
+            MapLocation::from_offset(content, 25),
+            MapLocation::File { line: 3, column: 8 }
+        );
+    }
+}
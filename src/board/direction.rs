@@ -56,6 +56,13 @@ impl Direction {
         Some(data)
     }
 
+    /// Direction to go from one cell to an adjacent one
+    pub fn between(from: &UVec2, to: &UVec2) -> Option<Self> {
+        Self::ADJACENT
+            .into_iter()
+            .find(|dir| dir.move_point(from).as_ref() == Some(to))
+    }
+
     #[allow(dead_code)]
     pub fn as_vec2(self) -> Vec2 {
         match self {
@@ -0,0 +1,133 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::{
+    board::{
+        board::Board,
+        combat::distance,
+        map::{AttackRange, Terrain, Unit},
+    },
+    interactive::{Owner, PlayerId},
+};
+
+impl Board {
+    /// The map is played with fog of war, enemy units are only seen within the
+    /// vision of the player
+    pub fn has_fog(&self) -> bool {
+        self.map().settings.fog
+    }
+
+    /// Cells the unit sees from the position, some units see farther from mountains
+    pub fn unit_vision(&self, unit: &Unit, pos: UVec2) -> u32 {
+        let stats = self.rules().unit(unit.unit_type);
+        match self.get(&pos) {
+            Some(Terrain::Mountain) => stats.vision + stats.mountain_vision,
+            _ => stats.vision,
+        }
+    }
+
+    /// Cells seen by the player and its team with fog of war, from their units and
+    /// buildings. The terrain hiding units is only seen from next to it.
+    pub fn visible_cells(&self, player: PlayerId) -> HashSet<UVec2> {
+        let team = self.team(player);
+        let allied = |owner: Owner| owner.0 != 0 && self.team(owner.0) == team;
+        let units = self
+            .units
+            .iter()
+            .filter(|(_, unit)| allied(unit.owner))
+            .map(|(pos, unit)| (*pos, self.unit_vision(unit, *pos)));
+        let buildings = self
+            .buildings
+            .iter()
+            .filter(|(_, building)| allied(building.owner))
+            .map(|(pos, building)| (*pos, self.rules().building(building.build_type).vision));
+
+        let mut visible = HashSet::new();
+        for (pos, vision) in units.chain(buildings) {
+            visible.extend(
+                self.cells_in_range(
+                    pos,
+                    AttackRange {
+                        min: 0,
+                        max: vision,
+                    },
+                )
+                .into_iter()
+                .filter(|cell| {
+                    distance(pos, *cell) <= 1 || !self.get(cell).is_some_and(Terrain::hides_units)
+                }),
+            );
+        }
+        visible
+    }
+
+    /// The player knows about the unit in the position, its team units are always
+    /// known and without fog every unit is
+    pub fn sees_unit(&self, player: PlayerId, pos: &UVec2, visible: &HashSet<UVec2>) -> bool {
+        !self.has_fog()
+            || visible.contains(pos)
+            || self
+                .units
+                .get(pos)
+                .is_some_and(|unit| self.team(unit.owner.0) == self.team(player))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::test_utils::{make_board, row_map};
+
+    const FOG_TABLES: &str = "[players]
+        1 = {}
+        2 = {}
+        [settings]
+        fog = true";
+
+    fn sorted(cells: HashSet<UVec2>) -> Vec<u32> {
+        let mut cells: Vec<u32> = cells.into_iter().map(|cell| cell.x).collect();
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn test_unit_vision() {
+        let board = make_board(&row_map(
+            &["p", "p", "p", "p", "p", "p"],
+            "6x1 = {type=\"city\", owner=1}",
+            "1x1 = {type=\"infantry\", owner=1}
+            4x1 = {type=\"maintank\", owner=2}",
+            FOG_TABLES,
+        ));
+
+        assert!(board.has_fog());
+        assert_eq!(sorted(board.visible_cells(1)), vec![0, 1, 2, 5]);
+        assert!(!board.sees_unit(1, &uvec2(3, 0), &board.visible_cells(1)));
+        assert!(board.sees_unit(2, &uvec2(3, 0), &board.visible_cells(2)));
+        assert_eq!(sorted(board.visible_cells(2)), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_mountain_and_forest() {
+        let board = make_board(&row_map(
+            &["m", "p", "p", "p", "f", "p"],
+            "",
+            "1x1 = {type=\"infantry\", owner=1}
+            5x1 = {type=\"infantry\", owner=2}",
+            FOG_TABLES,
+        ));
+
+        assert_eq!(
+            board.unit_vision(&board.units[&uvec2(0, 0)], uvec2(0, 0)),
+            5
+        );
+        assert_eq!(
+            sorted(board.visible_cells(1)),
+            vec![0, 1, 2, 3, 5],
+            "The forest is only seen from next to it"
+        );
+        assert!(!board.sees_unit(1, &uvec2(4, 0), &board.visible_cells(1)));
+        assert_eq!(sorted(board.visible_cells(2)), vec![2, 3, 4, 5]);
+    }
+}
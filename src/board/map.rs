@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-
 use bevy::{
     asset::{AssetLoader, LoadContext, io::Reader},
     prelude::*,
@@ -8,12 +6,18 @@ use thiserror::Error;
 use toml::Table;
 
 use crate::{
-    board::{Board, Direction, terrain::TileTerrain},
-    interactive::{Income, Life, Movement, MovementType, Owner},
+    assets::FileAssets,
+    board::{
+        diagnostics::{MapDiagnostics, MapLocation},
+        rules::Rules,
+        terrain::TileTerrain,
+        validator::validate_map,
+    },
+    interactive::{Income, Life, MovementType, Owner, PlayerId},
     matrix::Matrix,
 };
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Terrain {
     Plane,
     Road,
@@ -21,9 +25,10 @@ pub enum Terrain {
     Sea,
     Beach,
     Forest,
-    //River,
-    //Wall,
-    //BreakableWall(bool)
+    Bridge,
+    River,
+    Wall,
+    BreakableWall(Life),
 }
 
 pub struct UnknownTerrain(String);
@@ -35,35 +40,135 @@ impl TryFrom<&str> for Terrain {
             "p" => Ok(Terrain::Plane),
             "r" => Ok(Terrain::Road),
             "m" => Ok(Terrain::Mountain),
-            //"b" => Ok(Terrain::Bridge),
+            "b" => Ok(Terrain::Bridge),
             "B" => Ok(Terrain::Beach),
-            //"w" => Ok(Terrain::Wall),
             "f" => Ok(Terrain::Forest),
             "s" => Ok(Terrain::Sea),
-            // "b" => Ok(Terrain::Beach),
-            // "rv" => Ok(Terrain::River),
-            // "w" => Ok(Terrain::Wall),
-            // "bw" => Ok(Terrain::BreakableWall(false)),
-            // "bwd" => Ok(Terrain::BreakableWall(true)),
-            value => Err(UnknownTerrain(value.into())),
+            "rv" => Ok(Terrain::River),
+            "w" => Ok(Terrain::Wall),
+            "bw" => Ok(Terrain::BreakableWall(Life::new())),
+            value => value
+                .strip_prefix("bw:")
+                .and_then(|life| life.parse().ok())
+                .filter(|life| (1..=100).contains(life))
+                .map(|life| Terrain::BreakableWall(Life(life)))
+                .ok_or_else(|| UnknownTerrain(value.into())),
+        }
+    }
+}
+
+impl Terrain {
+    /// One terrain of each kind, breakable walls with all their life
+    pub const KINDS: [Terrain; 10] = [
+        Terrain::Plane,
+        Terrain::Road,
+        Terrain::Mountain,
+        Terrain::Sea,
+        Terrain::Beach,
+        Terrain::Forest,
+        Terrain::Bridge,
+        Terrain::River,
+        Terrain::Wall,
+        Terrain::BreakableWall(Life(100)),
+    ];
+
+    /// The terrain without its state, to look it up in the rules
+    pub fn kind(&self) -> Terrain {
+        match self {
+            Terrain::BreakableWall(_) => Terrain::BreakableWall(Life::new()),
+            terrain => *terrain,
+        }
+    }
+
+    /// Name used to represent the terrain in the rules files
+    pub fn name(&self) -> &'static str {
+        match self {
+            Terrain::Plane => "plain",
+            Terrain::Road => "road",
+            Terrain::Mountain => "mountain",
+            Terrain::Sea => "sea",
+            Terrain::Beach => "beach",
+            Terrain::Forest => "forest",
+            Terrain::Bridge => "bridge",
+            Terrain::River => "river",
+            Terrain::Wall => "wall",
+            Terrain::BreakableWall(_) => "breakable_wall",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Terrain> {
+        Self::KINDS
+            .into_iter()
+            .find(|terrain| terrain.name() == name)
+    }
+
+    /// Code used to represent the terrain in the map files, damaged breakable walls
+    /// keep their life after the code, like `bw:70`
+    pub fn code(&self) -> String {
+        let code = match self {
+            Terrain::Plane => "p",
+            Terrain::Road => "r",
+            Terrain::Mountain => "m",
+            Terrain::Beach => "B",
+            Terrain::Forest => "f",
+            Terrain::Sea => "s",
+            Terrain::Bridge => "b",
+            Terrain::River => "rv",
+            Terrain::Wall => "w",
+            Terrain::BreakableWall(life) if *life == Life::new() => "bw",
+            Terrain::BreakableWall(life) => return format!("bw:{}", life.0),
+        };
+        code.to_string()
+    }
+
+    /// If buildings can be placed over this terrain
+    pub fn allows_buildings(&self) -> bool {
+        !matches!(
+            self,
+            Terrain::Sea
+                | Terrain::River
+                | Terrain::Bridge
+                | Terrain::Wall
+                | Terrain::BreakableWall(_)
+        )
+    }
+
+    /// Terrain after receiving the damage, breakable walls turn into plain when
+    /// they run out of life, the rest of the terrain can not be damaged
+    pub fn damage(self, damage: u8) -> Self {
+        match self {
+            Terrain::BreakableWall(Life(life)) if life > damage => {
+                Terrain::BreakableWall(Life(life - damage))
+            }
+            Terrain::BreakableWall(_) => Terrain::Plane,
+            terrain => terrain,
         }
     }
+
+    /// With fog of war the units on the terrain are only seen from next to them
+    pub fn hides_units(&self) -> bool {
+        matches!(self, Terrain::Forest)
+    }
 }
-#[derive(Debug, Clone, Copy)]
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Building {
     pub owner: Owner,
-    pub income: Income,
+    /// Income set by the map, the one of the rules is used otherwise
+    pub income: Option<Income>,
     pub build_type: BuildingType,
 }
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum BuildingType {
     City,
-    //Town,
+    Town,
     Factory,
     Headquarters,
-    //Port,
-    //Airport,
-    //OilRig,
+    Port,
+    Airport,
+    OilRig,
+    Lab,
+    ComTower,
 }
 
 pub struct UnknownBuildingType;
@@ -74,80 +179,172 @@ impl TryFrom<&str> for BuildingType {
         match value.to_lowercase().as_str() {
             "headquarters" => Ok(Self::Headquarters),
             "city" => Ok(Self::City),
+            "town" => Ok(Self::Town),
             "factory" => Ok(Self::Factory),
+            "port" => Ok(Self::Port),
+            "airport" => Ok(Self::Airport),
+            "oilrig" => Ok(Self::OilRig),
+            "lab" => Ok(Self::Lab),
+            "comtower" => Ok(Self::ComTower),
             _ => Err(UnknownBuildingType),
         }
     }
 }
+
+impl BuildingType {
+    pub const ALL: [BuildingType; 9] = [
+        Self::City,
+        Self::Town,
+        Self::Factory,
+        Self::Headquarters,
+        Self::Port,
+        Self::Airport,
+        Self::OilRig,
+        Self::Lab,
+        Self::ComTower,
+    ];
+
+    /// Coordinates in the terrain sheet of the bottom half of the sprite
+    pub fn tile(&self) -> UVec2 {
+        match self {
+            Self::City => uvec2(0, 37),
+            Self::Town => uvec2(1, 37),
+            Self::Lab => uvec2(2, 37),
+            Self::OilRig => uvec2(4, 37),
+            Self::Airport => uvec2(8, 37),
+            Self::Factory => uvec2(9, 37),
+            Self::Port => uvec2(10, 37),
+            Self::ComTower => uvec2(11, 37),
+            Self::Headquarters => uvec2(12, 37),
+        }
+    }
+
+    /// The sprites are two tiles high, the top half is drawn over the next cell
+    pub fn top_tile(&self) -> UVec2 {
+        self.tile() - uvec2(0, 1)
+    }
+
+    /// Name used to represent the building in the map files
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Headquarters => "headquarters",
+            Self::City => "city",
+            Self::Town => "town",
+            Self::Factory => "factory",
+            Self::Port => "port",
+            Self::Airport => "airport",
+            Self::OilRig => "oilrig",
+            Self::Lab => "lab",
+            Self::ComTower => "comtower",
+        }
+    }
+
+    /// If the building can be placed over the terrain
+    pub fn allowed_on(&self, terrain: &Terrain) -> bool {
+        match self {
+            Self::OilRig => *terrain == Terrain::Sea,
+            _ => terrain.allows_buildings(),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Unit {
     pub owner: Owner,
     pub health: Life,
     pub unit_type: UnitType,
-    pub movement: Movement,
-}
-
-impl Unit {
-    pub fn get_movements(&self,pos:UVec2, board: &Board) -> Vec<PossibleMovement> {
-        let mut movements : HashMap<UVec2, PossibleMovement> = HashMap::default();
-        let mut pending_check = vec![PossibleMovement{
-            cost: 0,
-            layer: 0,
-            position: pos,
-        }];
-        let total_movement = self.movement.movements;
-        while let Some(to_check) = pending_check.pop() {
-            let is_new_or_better = match movements.get(&to_check.position) {
-                Some(existing) => existing.cost>to_check.cost,
-                None => true
-            };
-            if is_new_or_better {
-                for dir in Direction::ADJACENT {
-                    bevy::log::info!("Direction: {:?}", dir);
-                    let Some(new_pos) = dir.move_point(&to_check.position) else {
-                        continue
-                    };
-                    let Some(terrain) = board.get(&new_pos) else {
-                        continue;
-                    };
-                    let Some(move_cost) = self.movement.mov_type.cost(terrain) else {
-                        continue;
-                    };
-                    let new_cost = to_check.cost + move_cost;
-                    bevy::log::info!("New Cost: {}", new_cost);
-                    if new_cost < total_movement{
-                        pending_check.push(PossibleMovement { position: new_pos, layer: to_check.layer+1, cost: new_cost });
-                    }
-                }
-                movements.insert(to_check.position, to_check);
-            }
-        }
-        bevy::log::info!("We have possible movements! {}", movements.len());
-        movements.into_iter().map(|(_, movement)| movement).collect()
-    }
+    /// Shots of the main weapon fired since the last resupply
+    pub ammo_used: u32,
+    /// Fuel burnt since the last resupply
+    pub fuel_used: u32,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum UnitType {
     Infantry,
     Mech,
     Reccon,
     Tank,
+    MainTank,
+    WarTank,
+    Apc,
+    Artillery,
+    RocketLauncher,
+    AntiAir,
+    Missile,
+    Fighter,
+    Bomber,
+    BCopter,
+    TCopter,
+    Battlecruiser,
+    Cruiser,
+    Lander,
+    Sub,
+    Carrier,
+    SupplyShip,
 }
 
-pub struct PossibleMovement {
-    pub position: UVec2,
-    pub layer: u32,
-    pub cost: u32
+/// Minimum and maximum distance, in cells, a unit can attack at
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct AttackRange {
+    pub min: u32,
+    pub max: u32,
 }
 
-impl UnitType {
-    pub fn get_movement(&self) -> u32 {
-        match self {
-            Self::Infantry => 30,
-            Self::Mech => 25,
-            Self::Reccon => 50,
-            Self::Tank => 45,
+impl AttackRange {
+    /// Indirect units can not fire after moving nor fire back
+    pub fn is_indirect(&self) -> bool {
+        self.min > 1
+    }
+
+    pub fn contains(&self, distance: u32) -> bool {
+        (self.min..=self.max).contains(&distance)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct UnitStats {
+    pub cost: u32,
+    pub movement: u32,
+    pub mov_type: MovementType,
+    /// Cells the unit can see around it with fog of war
+    pub vision: u32,
+    /// Extra vision while standing on a mountain
+    pub mountain_vision: u32,
+    pub fuel: u32,
+    /// Shots of the main weapon, zero for units with only a machine gun or unarmed
+    pub ammo: u32,
+    /// None for units that can not attack
+    pub attack_range: Option<AttackRange>,
+}
+
+impl UnitStats {
+    pub fn class(&self) -> UnitClass {
+        match self.mov_type {
+            MovementType::Air => UnitClass::Air,
+            MovementType::Ship | MovementType::Lander => UnitClass::Sea,
+            _ => UnitClass::Land,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UnitClass {
+    Land,
+    Sea,
+    Air,
+}
+
+pub struct UnknownUnitClass;
+impl TryFrom<&str> for UnitClass {
+    type Error = UnknownUnitClass;
+
+    fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "land" => Ok(Self::Land),
+            "sea" => Ok(Self::Sea),
+            "air" => Ok(Self::Air),
+            _ => Err(UnknownUnitClass),
         }
     }
 }
@@ -157,15 +354,136 @@ impl TryFrom<&str> for UnitType {
     type Error = UnknownUnitType;
 
     fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
-        match value.to_lowercase().as_str() {
-            "infantry" => Ok(Self::Infantry),
-            "mech" => Ok(Self::Mech),
-            _ => Err(UnknownUnitType),
+        let value = value.to_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|unit_type| unit_type.name() == value)
+            .ok_or(UnknownUnitType)
+    }
+}
+
+impl UnitType {
+    pub const ALL: [UnitType; 21] = [
+        Self::Infantry,
+        Self::Mech,
+        Self::Reccon,
+        Self::Tank,
+        Self::MainTank,
+        Self::WarTank,
+        Self::Apc,
+        Self::Artillery,
+        Self::RocketLauncher,
+        Self::AntiAir,
+        Self::Missile,
+        Self::Fighter,
+        Self::Bomber,
+        Self::BCopter,
+        Self::TCopter,
+        Self::Battlecruiser,
+        Self::Cruiser,
+        Self::Lander,
+        Self::Sub,
+        Self::Carrier,
+        Self::SupplyShip,
+    ];
+
+    /// Infantry units can capture buildings
+    pub fn can_capture(&self) -> bool {
+        matches!(self, Self::Infantry | Self::Mech)
+    }
+
+    /// Number of units it can carry
+    pub fn transport_capacity(&self) -> usize {
+        match self {
+            Self::Apc | Self::TCopter => 1,
+            Self::Lander => 2,
+            _ => 0,
+        }
+    }
+
+    pub fn can_carry(&self, other: UnitType, rules: &Rules) -> bool {
+        match self {
+            Self::Apc | Self::TCopter => other.can_capture(),
+            Self::Lander => rules.unit(other).class() == UnitClass::Land,
+            _ => false,
+        }
+    }
+
+    /// Refills the fuel and ammo of the adjacent units
+    pub fn supplies(&self) -> bool {
+        matches!(self, Self::Apc | Self::SupplyShip)
+    }
+
+    /// Name used to represent the unit in the map files
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Infantry => "infantry",
+            Self::Mech => "mech",
+            Self::Reccon => "recon",
+            Self::Tank => "tank",
+            Self::MainTank => "maintank",
+            Self::WarTank => "wartank",
+            Self::Apc => "apc",
+            Self::Artillery => "artillery",
+            Self::RocketLauncher => "rocketlauncher",
+            Self::AntiAir => "antiair",
+            Self::Missile => "missile",
+            Self::Fighter => "fighter",
+            Self::Bomber => "bomber",
+            Self::BCopter => "bcopter",
+            Self::TCopter => "tcopter",
+            Self::Battlecruiser => "battlecruiser",
+            Self::Cruiser => "cruiser",
+            Self::Lander => "lander",
+            Self::Sub => "sub",
+            Self::Carrier => "carrier",
+            Self::SupplyShip => "supplyship",
+        }
+    }
+
+    pub fn sprite(&self) -> FileAssets {
+        match self {
+            Self::Infantry => FileAssets::ImagesGameUnitsInfantryPng,
+            Self::Mech => FileAssets::ImagesGameUnitsMechPng,
+            Self::Reccon => FileAssets::ImagesGameUnitsReconPng,
+            Self::Tank => FileAssets::ImagesGameUnitsTankPng,
+            Self::MainTank => FileAssets::ImagesGameUnitsMaintankPng,
+            Self::WarTank => FileAssets::ImagesGameUnitsWartankPng,
+            Self::Apc => FileAssets::ImagesGameUnitsApcPng,
+            Self::Artillery => FileAssets::ImagesGameUnitsArtilleryPng,
+            Self::RocketLauncher => FileAssets::ImagesGameUnitsRocketlauncherPng,
+            Self::AntiAir => FileAssets::ImagesGameUnitsAntiairPng,
+            Self::Missile => FileAssets::ImagesGameUnitsMissilePng,
+            Self::Fighter => FileAssets::ImagesGameUnitsFighterPng,
+            Self::Bomber => FileAssets::ImagesGameUnitsBomberPng,
+            Self::BCopter => FileAssets::ImagesGameUnitsBcopterPng,
+            Self::TCopter => FileAssets::ImagesGameUnitsTcopterPng,
+            Self::Battlecruiser => FileAssets::ImagesGameUnitsBattlecruiserPng,
+            Self::Cruiser => FileAssets::ImagesGameUnitsCruiserPng,
+            Self::Lander => FileAssets::ImagesGameUnitsLanderPng,
+            Self::Sub => FileAssets::ImagesGameUnitsSubPng,
+            Self::Carrier => FileAssets::ImagesGameUnitsCarrierPng,
+            Self::SupplyShip => FileAssets::ImagesGameUnitsSupplyshipPng,
+        }
+    }
+
+    /// Columns and rows of 32 pixels frames in the sprite sheet, the first one is
+    /// the unit standing still
+    pub fn sheet_size(&self) -> UVec2 {
+        match self {
+            Self::Infantry | Self::Mech => uvec2(5, 8),
+            Self::Apc => uvec2(30, 8),
+            Self::Artillery => uvec2(6, 8),
+            Self::BCopter => uvec2(8, 9),
+            Self::Missile => uvec2(7, 8),
+            Self::RocketLauncher => uvec2(10, 8),
+            Self::Tank => uvec2(10, 9),
+            _ => uvec2(1, 1),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct MapCell {
     pub terrain: Terrain,
     pub building: Option<Building>,
@@ -182,17 +500,177 @@ impl Default for MapCell {
     }
 }
 
-#[derive(Asset, TypePath, Debug, Clone)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PlayerColor {
+    Red,
+    Blue,
+    Green,
+    Yellow,
+    Black,
+}
+
+impl PlayerColor {
+    pub const ALL: [PlayerColor; 5] = [
+        PlayerColor::Red,
+        PlayerColor::Blue,
+        PlayerColor::Green,
+        PlayerColor::Yellow,
+        PlayerColor::Black,
+    ];
+
+    /// Name used to represent the color in the map files
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Red => "red",
+            Self::Blue => "blue",
+            Self::Green => "green",
+            Self::Yellow => "yellow",
+            Self::Black => "black",
+        }
+    }
+
+    /// Tint applied to the sprites of the player
+    pub fn tint(&self) -> Color {
+        match self {
+            Self::Red => Color::srgb(1.0, 0.6, 0.6),
+            Self::Blue => Color::srgb(0.6, 0.7, 1.0),
+            Self::Green => Color::srgb(0.6, 1.0, 0.6),
+            Self::Yellow => Color::srgb(1.0, 1.0, 0.55),
+            Self::Black => Color::srgb(0.5, 0.5, 0.5),
+        }
+    }
+}
+
+pub struct UnknownPlayerColor;
+impl TryFrom<&str> for PlayerColor {
+    type Error = UnknownPlayerColor;
+
+    fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "red" => Ok(Self::Red),
+            "blue" => Ok(Self::Blue),
+            "green" => Ok(Self::Green),
+            "yellow" => Ok(Self::Yellow),
+            "black" => Ok(Self::Black),
+            _ => Err(UnknownPlayerColor),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Weather {
+    #[default]
+    Clear,
+    Rain,
+    Snow,
+    Sandstorm,
+}
+
+impl Weather {
+    /// Name used to represent the weather in the map files
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Clear => "clear",
+            Self::Rain => "rain",
+            Self::Snow => "snow",
+            Self::Sandstorm => "sandstorm",
+        }
+    }
+}
+
+pub struct UnknownWeather;
+impl TryFrom<&str> for Weather {
+    type Error = UnknownWeather;
+
+    fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "clear" => Ok(Self::Clear),
+            "rain" => Ok(Self::Rain),
+            "snow" => Ok(Self::Snow),
+            "sandstorm" => Ok(Self::Sandstorm),
+            _ => Err(UnknownWeather),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct MapMetadata {
+    pub name: String,
+    pub author: String,
+    pub description: String,
+    /// Recommended number of players
+    pub players: u8,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PlayerSlot {
+    pub id: PlayerId,
+    pub team: u8,
+    pub color: PlayerColor,
+    /// Funds available at the start of the match
+    pub funds: u32,
+}
+
+impl PlayerSlot {
+    pub fn new(id: PlayerId) -> Self {
+        Self {
+            id,
+            team: id,
+            color: PlayerColor::ALL[(id.max(1) as usize - 1) % PlayerColor::ALL.len()],
+            funds: 0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct MapSettings {
+    pub day_limit: Option<u32>,
+    pub fog: bool,
+    pub weather: Weather,
+}
+
+#[derive(Asset, TypePath, Debug, PartialEq, Clone)]
 pub struct Map {
     pub cells: Matrix<MapCell>,
+    pub metadata: MapMetadata,
+    pub players: Vec<PlayerSlot>,
+    pub settings: MapSettings,
 }
 
 impl Map {
     pub fn empty() -> Self {
+        Self::from_cells(Matrix::new(1, 1, MapCell::default()))
+    }
+
+    /// Builds a map with default metadata and settings, creating a player slot for
+    /// every owner found in the cells
+    pub fn from_cells(cells: Matrix<MapCell>) -> Self {
+        let mut owners: Vec<PlayerId> = cells
+            .iter()
+            .flat_map(|cell| {
+                [
+                    cell.building.map(|building| building.owner.0),
+                    cell.unit.map(|unit| unit.owner.0),
+                ]
+            })
+            .flatten()
+            .filter(|owner| *owner != 0)
+            .collect();
+        owners.sort();
+        owners.dedup();
+        let players: Vec<_> = owners.into_iter().map(PlayerSlot::new).collect();
+
         Self {
-            cells: Matrix::new(1, 1, MapCell::default()),
+            cells,
+            metadata: MapMetadata {
+                players: players.len() as u8,
+                ..default()
+            },
+            players,
+            settings: MapSettings::default(),
         }
     }
+
     pub fn width(&self) -> usize {
         self.cells.cols()
     }
@@ -226,12 +704,15 @@ impl Into<Matrix<TileTerrain>> for &Map {
 #[derive(Default)]
 pub struct MapAssetLoader;
 
+/// Biggest width or height accepted for a map
+const MAX_MAP_SIZE: usize = 1024;
+
 #[derive(Debug, Error)]
 pub enum MapLoaderError {
     #[error("Could not read file: {0}")]
     Io(#[from] std::io::Error),
-    #[error("Invalid map format: {0}")]
-    ParseError(String),
+    #[error("Invalid map format:\n{0}")]
+    Invalid(MapDiagnostics),
 }
 
 impl AssetLoader for MapAssetLoader {
@@ -247,236 +728,771 @@ impl AssetLoader for MapAssetLoader {
         &self,
         reader: &mut dyn Reader,
         _settings: &Self::Settings,
-        _load_context: &mut LoadContext,
+        load_context: &mut LoadContext,
     ) -> impl bevy::tasks::ConditionalSendFuture<Output = std::result::Result<Self::Asset, Self::Error>>
     {
         Box::pin(async move {
             let mut bytes = Vec::new();
             reader.read_to_end(&mut bytes).await?;
-            let content = std::str::from_utf8(&bytes)
-                .map_err(|e| MapLoaderError::ParseError(e.to_string()))?;
+            let content = std::str::from_utf8(&bytes).map_err(|err| {
+                let valid = std::str::from_utf8(&bytes[..err.valid_up_to()]).unwrap_or_default();
+                let mut diagnostics = MapDiagnostics::default();
+                diagnostics.error(
+                    Some(MapLocation::from_offset(valid, valid.len())),
+                    "Invalid UTF-8 content",
+                );
+                MapLoaderError::Invalid(diagnostics)
+            })?;
 
-            parse_map(content)
+            let map = parse_map(content)?;
+            // The rules asset may not be loaded yet, the built-in rules are close enough
+            let diagnostics = validate_map(&map, Rules::builtin());
+            if diagnostics.has_errors() {
+                return Err(MapLoaderError::Invalid(diagnostics));
+            }
+            for diagnostic in diagnostics.0 {
+                bevy::log::warn!("{}: {diagnostic}", load_context.path().display());
+            }
+            Ok(map)
         })
     }
 }
 
 fn parse_position(key: &str) -> Option<(usize, usize)> {
-    let coords: Vec<_> = key.split('x').collect();
-    if coords.len() < 2 {
+    let (x, y) = key.split_once('x')?;
+    let x: usize = x.trim().parse().ok()?;
+    let y: usize = y.trim().parse().ok()?;
+    if x == 0 || y == 0 {
         None
     } else {
-        let x: usize = coords[0].parse().unwrap();
-        let y: usize = coords[1].parse().unwrap();
-        if x == 0 || y == 0 {
-            None
-        } else {
-            Some((x - 1, y - 1))
-        }
+        Some((x - 1, y - 1))
     }
 }
 
-fn parse_v1_building(building_source: &Table) -> Result<Building, MapLoaderError> {
-    let owner_id = building_source
-        .get("owner")
-        .map(|d| d.as_integer())
-        .flatten()
-        .unwrap_or(0);
-
-    if owner_id < 0 {
-        return Err(MapLoaderError::ParseError(
-            "Owner ID must be a positive number".into(),
-        ));
-    }
-    let Some(building_type) = building_source.get("type").map(|d| d.as_str()).flatten() else {
-        return Err(MapLoaderError::ParseError(
-            "Building type must be specified".into(),
-        ));
+fn parse_v1_building(
+    building_source: &Table,
+    location: &MapLocation,
+    diagnostics: &mut MapDiagnostics,
+) -> Option<Building> {
+    let owner = match building_source.get("owner") {
+        None => Some(Owner(0)),
+        Some(owner_id) => {
+            let owner = owner_id
+                .as_integer()
+                .and_then(|owner_id| PlayerId::try_from(owner_id).ok())
+                .map(Owner);
+            if owner.is_none() {
+                diagnostics.error(Some(location.clone()), "Owner ID must be a positive number");
+            }
+            owner
+        }
     };
 
-    let build_type = BuildingType::try_from(building_type).map_err(|_err| {
-        MapLoaderError::ParseError(format!("Invalid building type: {building_type}"))
-    })?;
+    let build_type = match building_source.get("type").and_then(|d| d.as_str()) {
+        None => {
+            diagnostics.error(Some(location.clone()), "Building type must be specified");
+            None
+        }
+        Some(building_type) => BuildingType::try_from(building_type)
+            .map_err(|_err| {
+                diagnostics.error(
+                    Some(location.clone()),
+                    format!("Invalid building type: {building_type}"),
+                )
+            })
+            .ok(),
+    };
 
-    Ok(Building {
+    let build_type = build_type?;
+    Some(Building {
         build_type,
-        owner: Owner(owner_id as u8),
-        income: Income(1000),
+        owner: owner?,
+        income: None,
     })
 }
-fn parse_v1_unit(unit_source: &Table) -> Result<Unit, MapLoaderError> {
-    let Some(owner_id) = unit_source.get("owner").map(|d| d.as_integer()).flatten() else {
-        return Err(MapLoaderError::ParseError(
-            "Owner ID is required and should be a positive number".into(),
-        ));
-    };
-    if owner_id < 1 {
-        return Err(MapLoaderError::ParseError(
-            "Owner Id must be equals or greater than 1 for units".into(),
-        ));
-    };
 
-    let Some(unit_type) = unit_source.get("type").map(|d| d.as_str()).flatten() else {
-        return Err(MapLoaderError::ParseError(
-            "Unit type must be specified".into(),
-        ));
+fn parse_v2_building(
+    building_source: &Table,
+    location: &MapLocation,
+    diagnostics: &mut MapDiagnostics,
+) -> Option<Building> {
+    let building = parse_v1_building(building_source, location, diagnostics);
+    let income = match building_source.get("income") {
+        None => Some(None),
+        Some(income) => {
+            let income = income
+                .as_integer()
+                .and_then(|income| u32::try_from(income).ok())
+                .map(Income);
+            if income.is_none() {
+                diagnostics.error(Some(location.clone()), "Income must be a positive number");
+            }
+            income.map(Some)
+        }
     };
-    let unit_type = UnitType::try_from(unit_type)
-        .map_err(|_err| MapLoaderError::ParseError(format!("Invalid unit type: {unit_type}")))?;
-
-    let health = unit_source
-        .get("life")
-        .map(|d| d.as_integer())
-        .flatten()
-        .unwrap_or(100);
-    if health < 1 {
-        return Err(MapLoaderError::ParseError(
-            "Life of a unit must be greater than 0".into(),
-        ));
-    }
 
-    Ok(Unit {
-        owner: Owner(owner_id as u8),
-        health: Life(health as u8),
-        unit_type,
-        movement: Movement { mov_type: MovementType::Foot, movements: 40 }
+    Some(Building {
+        income: income?,
+        ..building?
     })
 }
-fn parse_v1(map_source: &Table) -> Result<Map, MapLoaderError> {
-    let Some(width) = map_source.get("width").map(|d| d.as_integer()).flatten() else {
-        return Err(MapLoaderError::ParseError(
-            "Missing width or is not an integer".into(),
-        ));
+
+/// Ammo or fuel used by the unit since its last resupply, zero when missing
+fn parse_v2_used(
+    unit_source: &Table,
+    key: &str,
+    location: &MapLocation,
+    diagnostics: &mut MapDiagnostics,
+) -> Option<u32> {
+    match unit_source.get(key) {
+        None => Some(0),
+        Some(used) => {
+            let used = used.as_integer().and_then(|used| u32::try_from(used).ok());
+            if used.is_none() {
+                diagnostics.error(
+                    Some(location.clone()),
+                    format!("The {key} of a unit must be a positive number"),
+                );
+            }
+            used
+        }
+    }
+}
+
+fn parse_v1_unit(
+    unit_source: &Table,
+    location: &MapLocation,
+    diagnostics: &mut MapDiagnostics,
+) -> Option<Unit> {
+    let owner = match unit_source.get("owner").and_then(|d| d.as_integer()) {
+        None => {
+            diagnostics.error(
+                Some(location.clone()),
+                "Owner ID is required and should be a positive number",
+            );
+            None
+        }
+        Some(owner_id) => {
+            let owner = PlayerId::try_from(owner_id)
+                .ok()
+                .filter(|owner_id| *owner_id >= 1)
+                .map(Owner);
+            if owner.is_none() {
+                diagnostics.error(
+                    Some(location.clone()),
+                    "Owner Id must be equals or greater than 1 for units",
+                );
+            }
+            owner
+        }
     };
 
-    let Some(height) = map_source.get("height").map(|d| d.as_integer()).flatten() else {
-        return Err(MapLoaderError::ParseError(
-            "Missing height or is not an integer".into(),
-        ));
+    let unit_type = match unit_source.get("type").and_then(|d| d.as_str()) {
+        None => {
+            diagnostics.error(Some(location.clone()), "Unit type must be specified");
+            None
+        }
+        Some(unit_type) => UnitType::try_from(unit_type)
+            .map_err(|_err| {
+                diagnostics.error(
+                    Some(location.clone()),
+                    format!("Invalid unit type: {unit_type}"),
+                )
+            })
+            .ok(),
+    };
+
+    let health = match unit_source.get("life") {
+        None => Some(Life(100)),
+        Some(health) => {
+            let health = health
+                .as_integer()
+                .filter(|health| (1..=100).contains(health))
+                .map(|health| Life(health as u8));
+            if health.is_none() {
+                diagnostics.error(
+                    Some(location.clone()),
+                    "Life of a unit must be a number between 1 and 100",
+                );
+            }
+            health
+        }
     };
-    let width = width as usize;
-    let height = height as usize;
 
-    let mut map = Matrix::new(width, height, MapCell::default());
+    Some(Unit {
+        owner: owner?,
+        health: health?,
+        unit_type: unit_type?,
+        ammo_used: 0,
+        fuel_used: 0,
+    })
+}
 
-    let Some(terrain) = map_source.get("terrain").map(|d| d.as_array()).flatten() else {
-        return Err(MapLoaderError::ParseError(
-            "Missing terrain or is not an array".into(),
-        ));
+fn parse_v2_unit(
+    unit_source: &Table,
+    location: &MapLocation,
+    diagnostics: &mut MapDiagnostics,
+) -> Option<Unit> {
+    let unit = parse_v1_unit(unit_source, location, diagnostics);
+    let ammo_used = parse_v2_used(unit_source, "ammo_used", location, diagnostics);
+    let fuel_used = parse_v2_used(unit_source, "fuel_used", location, diagnostics);
+    Some(Unit {
+        ammo_used: ammo_used?,
+        fuel_used: fuel_used?,
+        ..unit?
+    })
+}
+
+fn parse_size(map_source: &Table, key: &str, diagnostics: &mut MapDiagnostics) -> Option<usize> {
+    let size = map_source
+        .get(key)
+        .and_then(|d| d.as_integer())
+        .and_then(|size| usize::try_from(size).ok())
+        .filter(|size| (1..=MAX_MAP_SIZE).contains(size));
+    if size.is_none() {
+        diagnostics.error(
+            Some(MapLocation::Property(key.into())),
+            format!("Missing {key} or is not an integer between 1 and {MAX_MAP_SIZE}"),
+        );
+    }
+    size
+}
+
+type ParseBuilding = fn(&Table, &MapLocation, &mut MapDiagnostics) -> Option<Building>;
+type ParseUnit = fn(&Table, &MapLocation, &mut MapDiagnostics) -> Option<Unit>;
+
+/// Reads the position of an entry in the units or buildings tables, checking that
+/// it is inside the map
+fn parse_entry<'a>(
+    key: &str,
+    value: &'a toml::Value,
+    location: &MapLocation,
+    size: (usize, usize),
+    diagnostics: &mut MapDiagnostics,
+) -> Option<((usize, usize), &'a Table)> {
+    let Some(coords) = parse_position(key) else {
+        diagnostics.error(
+            Some(location.clone()),
+            "Invalid coords, they should be written as XxY starting at 1",
+        );
+        return None;
+    };
+    if coords.0 >= size.0 || coords.1 >= size.1 {
+        diagnostics.error(
+            Some(location.clone()),
+            format!(
+                "Invalid coords, they are outside of the map ({}x{})",
+                size.0, size.1
+            ),
+        );
+        return None;
+    }
+    let Some(data) = value.as_table() else {
+        diagnostics.error(
+            Some(location.clone()),
+            "Invalid contents, it should be a table",
+        );
+        return None;
+    };
+    Some((coords, data))
+}
+
+fn parse_terrain(map_source: &Table, map: &mut Matrix<MapCell>, diagnostics: &mut MapDiagnostics) {
+    let (width, height) = map.size();
+    let Some(terrain) = map_source.get("terrain").and_then(|d| d.as_array()) else {
+        diagnostics.error(
+            Some(MapLocation::Property("terrain".into())),
+            "Missing terrain or is not an array",
+        );
+        return;
     };
     if terrain.len() != height {
-        return Err(MapLoaderError::ParseError(format!(
-            "Invalid terrain height {}, it doesn't match the property height {height}",
-            terrain.len()
-        )));
+        diagnostics.error(
+            Some(MapLocation::Property("terrain".into())),
+            format!(
+                "Invalid terrain height {}, it doesn't match the property height {height}",
+                terrain.len()
+            ),
+        );
     }
-    for idy in 0..height {
-        let Some(terrain_row) = terrain[idy].as_array() else {
-            return Err(MapLoaderError::ParseError(format!(
-                "Invalid terrain, row {idy} is not an array"
-            )));
+    for (idy, terrain_row) in terrain.iter().enumerate().take(height) {
+        let Some(terrain_row) = terrain_row.as_array() else {
+            diagnostics.error(
+                Some(MapLocation::TerrainRow(idy + 1)),
+                "Invalid terrain, the row is not an array",
+            );
+            continue;
         };
         if terrain_row.len() != width {
-            return Err(MapLoaderError::ParseError(format!(
-                "Invalid terrain width {} at row {idy}, it doesn't match the property width {width}",
-                terrain_row.len()
-            )));
-        }
-        for idx in 0..width {
-            let Some(cell) = terrain_row[idx].as_str() else {
-                return Err(MapLoaderError::ParseError(format!(
-                    "Invalid terrain at ({idx}, {idy})"
-                )));
+            diagnostics.error(
+                Some(MapLocation::TerrainRow(idy + 1)),
+                format!(
+                    "Invalid terrain width {}, it doesn't match the property width {width}",
+                    terrain_row.len()
+                ),
+            );
+        }
+        for (idx, cell) in terrain_row.iter().enumerate().take(width) {
+            let location = MapLocation::Terrain {
+                row: idy + 1,
+                column: idx + 1,
             };
-            let terrain_cell = Terrain::try_from(cell).map_err(|err| {
-                MapLoaderError::ParseError(format!("Unknown terrain type {}", err.0))
-            })?;
-            map[(idx, idy)] = MapCell {
-                terrain: terrain_cell,
-                building: None,
-                unit: None,
+            let Some(cell) = cell.as_str() else {
+                diagnostics.error(Some(location), "Invalid terrain, it should be a string");
+                continue;
             };
+            match Terrain::try_from(cell) {
+                Ok(terrain) => map[(idx, idy)].terrain = terrain,
+                Err(err) => {
+                    diagnostics.error(Some(location), format!("Unknown terrain type {}", err.0))
+                }
+            }
         }
     }
+}
+
+fn parse_cells(
+    map_source: &Table,
+    (parse_building, parse_unit): (ParseBuilding, ParseUnit),
+    diagnostics: &mut MapDiagnostics,
+) -> Option<Matrix<MapCell>> {
+    let width = parse_size(map_source, "width", diagnostics);
+    let height = parse_size(map_source, "height", diagnostics);
+    let size = (width?, height?);
+
+    let mut map = Matrix::new(size.0, size.1, MapCell::default());
+    parse_terrain(map_source, &mut map, diagnostics);
 
     let empty_list = Table::new();
     let units = map_source
         .get("units")
-        .map(|unit| unit.as_table())
-        .flatten()
+        .and_then(|unit| unit.as_table())
         .unwrap_or(&empty_list);
     for (key, value) in units.iter() {
-        let Some(coords) = parse_position(key) else {
-            return Err(MapLoaderError::ParseError(format!(
-                "Invalid coord in units: {key}"
-            )));
-        };
-        if coords.0 > width || coords.1 > height {
-            return Err(MapLoaderError::ParseError(format!(
-                "Invalid coords {:?}, they are bigger than ({},{})",
-                coords, width, height
-            )));
-        }
-        let Some(unit_data) = value.as_table() else {
-            return Err(MapLoaderError::ParseError(format!(
-                "Invalid contents in unit coords {:?}",
-                coords
-            )));
+        let location = MapLocation::Unit(key.clone());
+        let Some((coords, unit_data)) = parse_entry(key, value, &location, size, diagnostics)
+        else {
+            continue;
         };
-        map[coords].unit = Some(parse_v1_unit(unit_data)?)
+        map[coords].unit = parse_unit(unit_data, &location, diagnostics);
     }
 
-    let empty_list = Table::new();
     let buildings = map_source
         .get("buildings")
-        .map(|building| building.as_table())
-        .flatten()
+        .and_then(|building| building.as_table())
         .unwrap_or(&empty_list);
     for (key, value) in buildings.iter() {
-        let Some(coords) = parse_position(key) else {
-            return Err(MapLoaderError::ParseError(format!(
-                "Invalid coord in build: {key}"
-            )));
-        };
-        if coords.0 > width || coords.1 > height {
-            return Err(MapLoaderError::ParseError(format!(
-                "Invalid coords {:?}, they are bigger than ({},{})",
-                coords, width, height
-            )));
-        }
-        let Some(building_data) = value.as_table() else {
-            return Err(MapLoaderError::ParseError(format!(
-                "Invalid contents in build coords {:?}",
-                coords
-            )));
+        let location = MapLocation::Building(key.clone());
+        let Some((coords, building_data)) = parse_entry(key, value, &location, size, diagnostics)
+        else {
+            continue;
         };
-        map[coords].building = Some(parse_v1_building(building_data)?)
+        map[coords].building = parse_building(building_data, &location, diagnostics);
+    }
+
+    Some(map)
+}
+
+/// Version 1 has no metadata, players or settings, it is upgraded to the current
+/// format using the defaults and a player slot per owner
+fn parse_v1(map_source: &Table, diagnostics: &mut MapDiagnostics) -> Option<Map> {
+    Some(Map::from_cells(parse_cells(
+        map_source,
+        (parse_v1_building, parse_v1_unit),
+        diagnostics,
+    )?))
+}
+
+fn parse_v2_string(source: &Table, key: &str, diagnostics: &mut MapDiagnostics) -> String {
+    match source.get(key) {
+        None => String::new(),
+        Some(value) => match value.as_str() {
+            Some(value) => value.into(),
+            None => {
+                diagnostics.error(
+                    Some(MapLocation::Property(format!("metadata.{key}"))),
+                    "It must be a string",
+                );
+                String::new()
+            }
+        },
     }
+}
+
+fn parse_v2_metadata(
+    metadata_source: &Table,
+    players: &[PlayerSlot],
+    diagnostics: &mut MapDiagnostics,
+) -> MapMetadata {
+    let recommended_players = match metadata_source.get("players") {
+        None => players.len() as u8,
+        Some(value) => match value
+            .as_integer()
+            .and_then(|value| u8::try_from(value).ok())
+        {
+            Some(value) => value,
+            None => {
+                diagnostics.error(
+                    Some(MapLocation::Property("metadata.players".into())),
+                    "Recommended players must be a positive number",
+                );
+                0
+            }
+        },
+    };
 
-    Ok(Map { cells: map })
+    MapMetadata {
+        name: parse_v2_string(metadata_source, "name", diagnostics),
+        author: parse_v2_string(metadata_source, "author", diagnostics),
+        description: parse_v2_string(metadata_source, "description", diagnostics),
+        players: recommended_players,
+    }
 }
 
-fn parse_map(content: &str) -> Result<Map, MapLoaderError> {
-    let raw_file: Table = toml::from_str(content).map_err(|err| {
-        bevy::log::error!("Error loading map {err}");
-        MapLoaderError::ParseError("Invalid file format".into())
-    })?;
-    let version = raw_file.get("version").map_or_else(
-        || Err(MapLoaderError::ParseError("Version not found".into())),
-        |data| Ok(data.as_integer()),
-    )?;
+fn parse_v2_player(
+    key: &str,
+    player_source: &Table,
+    diagnostics: &mut MapDiagnostics,
+) -> Option<PlayerSlot> {
+    let location = MapLocation::Player(key.into());
+    let Some(id) = key.parse::<PlayerId>().ok().filter(|id| *id > 0) else {
+        diagnostics.error(
+            Some(location),
+            "Invalid player id, it must be equals or greater than 1",
+        );
+        return None;
+    };
+    let mut player = PlayerSlot::new(id);
+    let mut valid = true;
+
+    if let Some(team) = player_source.get("team") {
+        match team.as_integer().and_then(|team| u8::try_from(team).ok()) {
+            Some(team) => player.team = team,
+            None => {
+                diagnostics.error(Some(location.clone()), "Invalid team");
+                valid = false;
+            }
+        }
+    }
+
+    if let Some(color) = player_source.get("color") {
+        match color.as_str().map(PlayerColor::try_from) {
+            Some(Ok(color)) => player.color = color,
+            _ => {
+                diagnostics.error(Some(location.clone()), format!("Invalid color {color}"));
+                valid = false;
+            }
+        }
+    }
 
-    match version {
-        Some(1) => parse_v1(&raw_file),
-        _ => Err(MapLoaderError::ParseError(
-            format!("Unsupported map version {:?}", version).into(),
-        )),
+    if let Some(funds) = player_source.get("funds") {
+        match funds
+            .as_integer()
+            .and_then(|funds| u32::try_from(funds).ok())
+        {
+            Some(funds) => player.funds = funds,
+            None => {
+                diagnostics.error(Some(location), "Funds must be a positive number");
+                valid = false;
+            }
+        }
     }
+
+    valid.then_some(player)
+}
+
+fn parse_v2_settings(settings_source: &Table, diagnostics: &mut MapDiagnostics) -> MapSettings {
+    let mut settings = MapSettings::default();
+
+    if let Some(value) = settings_source.get("day_limit") {
+        settings.day_limit = value
+            .as_integer()
+            .and_then(|day_limit| u32::try_from(day_limit).ok())
+            .filter(|day_limit| *day_limit > 0);
+        if settings.day_limit.is_none() {
+            diagnostics.error(
+                Some(MapLocation::Property("settings.day_limit".into())),
+                "Day limit must be greater than 0",
+            );
+        }
+    }
+
+    if let Some(value) = settings_source.get("fog") {
+        match value.as_bool() {
+            Some(fog) => settings.fog = fog,
+            None => diagnostics.error(
+                Some(MapLocation::Property("settings.fog".into())),
+                "Fog must be a boolean",
+            ),
+        }
+    }
+
+    if let Some(value) = settings_source.get("weather") {
+        match value.as_str().map(Weather::try_from) {
+            Some(Ok(weather)) => settings.weather = weather,
+            _ => diagnostics.error(
+                Some(MapLocation::Property("settings.weather".into())),
+                format!("Invalid weather: {value}"),
+            ),
+        }
+    }
+
+    settings
+}
+
+fn parse_v2(map_source: &Table, diagnostics: &mut MapDiagnostics) -> Option<Map> {
+    let cells = parse_cells(map_source, (parse_v2_building, parse_v2_unit), diagnostics);
+
+    let mut players = None;
+    if let Some(players_source) = map_source.get("players") {
+        match players_source.as_table() {
+            None => diagnostics.error(
+                Some(MapLocation::Property("players".into())),
+                "Players must be a table",
+            ),
+            Some(players_source) => {
+                let mut slots = Vec::new();
+                for (key, value) in players_source.iter() {
+                    let Some(player_data) = value.as_table() else {
+                        diagnostics.error(
+                            Some(MapLocation::Player(key.clone())),
+                            "Invalid contents, it should be a table",
+                        );
+                        continue;
+                    };
+                    slots.extend(parse_v2_player(key, player_data, diagnostics));
+                }
+                slots.sort_by_key(|player: &PlayerSlot| player.id);
+                players = Some(slots);
+            }
+        }
+    }
+
+    let mut map = Map::from_cells(cells?);
+    if let Some(players) = players {
+        map.players = players;
+    }
+
+    let empty_table = Table::new();
+    let metadata = map_source
+        .get("metadata")
+        .and_then(|metadata| metadata.as_table())
+        .unwrap_or(&empty_table);
+    map.metadata = parse_v2_metadata(metadata, &map.players, diagnostics);
+
+    let settings = map_source
+        .get("settings")
+        .and_then(|settings| settings.as_table())
+        .unwrap_or(&empty_table);
+    map.settings = parse_v2_settings(settings, diagnostics);
+
+    Some(map)
+}
+
+/// Parses the contents of an `.abwm` file, upgrading old versions to the current
+/// format. All the problems found are reported together in the error.
+pub fn parse_map(content: &str) -> Result<Map, MapLoaderError> {
+    parse_map_with_version(content).map(|(map, _)| map)
+}
+
+/// Same as [`parse_map`], also returning the version of the format of the file
+pub fn parse_map_with_version(content: &str) -> Result<(Map, i64), MapLoaderError> {
+    let mut diagnostics = MapDiagnostics::default();
+    let map = parse_map_source(content, &mut diagnostics);
+    match map {
+        Some(map) if !diagnostics.has_errors() => Ok(map),
+        _ => Err(MapLoaderError::Invalid(diagnostics)),
+    }
+}
+
+fn parse_map_source(content: &str, diagnostics: &mut MapDiagnostics) -> Option<(Map, i64)> {
+    let raw_file: Table = match toml::from_str(content) {
+        Ok(raw_file) => raw_file,
+        Err(err) => {
+            let location = err
+                .span()
+                .map(|span| MapLocation::from_offset(content, span.start));
+            diagnostics.error(location, err.message());
+            return None;
+        }
+    };
+
+    match raw_file.get("version").map(|data| data.as_integer()) {
+        Some(Some(1)) => parse_v1(&raw_file, diagnostics).map(|map| (map, 1)),
+        Some(Some(2)) => parse_v2(&raw_file, diagnostics).map(|map| (map, 2)),
+        None => {
+            diagnostics.error(
+                Some(MapLocation::Property("version".into())),
+                "Version not found",
+            );
+            None
+        }
+        Some(version) => {
+            diagnostics.error(
+                Some(MapLocation::Property("version".into())),
+                format!("Unsupported map version {version:?}"),
+            );
+            None
+        }
+    }
+}
+
+fn write_position(pos: (usize, usize)) -> String {
+    format!("{}x{}", pos.0 + 1, pos.1 + 1)
+}
+
+fn write_v1_building(building: &Building) -> String {
+    let mut fields = vec![format!("type=\"{}\"", building.build_type.name())];
+    if building.owner.0 != 0 {
+        fields.push(format!("owner={}", building.owner.0));
+    }
+    format!("{{{}}}", fields.join(", "))
+}
+
+fn write_v2_building(building: &Building) -> String {
+    let mut fields = vec![format!("type=\"{}\"", building.build_type.name())];
+    if building.owner.0 != 0 {
+        fields.push(format!("owner={}", building.owner.0));
+    }
+    if let Some(income) = building.income {
+        fields.push(format!("income={}", income.0));
+    }
+    format!("{{{}}}", fields.join(", "))
+}
+
+fn write_v1_unit(unit: &Unit) -> String {
+    let mut fields = vec![
+        format!("type=\"{}\"", unit.unit_type.name()),
+        format!("owner={}", unit.owner.0),
+    ];
+    if unit.health.0 != 100 {
+        fields.push(format!("life={}", unit.health.0));
+    }
+    format!("{{{}}}", fields.join(", "))
+}
+
+fn write_v2_unit(unit: &Unit) -> String {
+    let mut fields = vec![
+        format!("type=\"{}\"", unit.unit_type.name()),
+        format!("owner={}", unit.owner.0),
+    ];
+    if unit.health.0 != 100 {
+        fields.push(format!("life={}", unit.health.0));
+    }
+    if unit.ammo_used != 0 {
+        fields.push(format!("ammo_used={}", unit.ammo_used));
+    }
+    if unit.fuel_used != 0 {
+        fields.push(format!("fuel_used={}", unit.fuel_used));
+    }
+    format!("{{{}}}", fields.join(", "))
+}
+
+fn write_string(value: &str) -> String {
+    toml::Value::String(value.into()).to_string()
+}
+
+fn write_terrain(lines: &mut Vec<String>, map: &Map) {
+    let (width, height) = map.get_size();
+    lines.push(format!("width={width}"));
+    lines.push(format!("height={height}"));
+    lines.push("terrain = [".to_string());
+    for idy in 0..height {
+        let row: Vec<_> = map
+            .cells
+            .row(idy)
+            .unwrap_or_default()
+            .iter()
+            .map(|cell| format!("\"{}\"", cell.terrain.code()))
+            .collect();
+        lines.push(format!("    [{}],", row.join(", ")));
+    }
+    lines.push("]".to_string());
+}
+
+type WriteBuilding = fn(&Building) -> String;
+type WriteUnit = fn(&Unit) -> String;
+
+fn write_cells(
+    lines: &mut Vec<String>,
+    map: &Map,
+    (write_building, write_unit): (WriteBuilding, WriteUnit),
+) {
+    lines.push("[buildings]".to_string());
+    for pos in map.cells.keys() {
+        if let Some(building) = &map.cells[pos].building {
+            lines.push(format!(
+                "{} = {}",
+                write_position(pos),
+                write_building(building)
+            ));
+        }
+    }
+
+    lines.push("[units]".to_string());
+    for pos in map.cells.keys() {
+        if let Some(unit) = &map.cells[pos].unit {
+            lines.push(format!("{} = {}", write_position(pos), write_unit(unit)));
+        }
+    }
+}
+
+/// Serializes the map into the version 1 `.abwm` format. Metadata, players, settings,
+/// income overrides and the ammo and fuel used by the units are lost, as they are
+/// not part of this version.
+pub fn write_map(map: &Map) -> String {
+    let mut lines = vec!["version=1".to_string()];
+    write_terrain(&mut lines, map);
+    write_cells(&mut lines, map, (write_v1_building, write_v1_unit));
+    lines.push(String::new());
+
+    lines.join("\n")
+}
+
+/// Serializes the map into the current `.abwm` format, the result can be read back
+/// with `parse_map`. Only the incomes set by the map and the ammo and fuel already
+/// used by the units are written.
+pub fn write_map_v2(map: &Map) -> String {
+    let mut lines = vec!["version=2".to_string()];
+    write_terrain(&mut lines, map);
+
+    lines.push("[metadata]".to_string());
+    lines.push(format!("name = {}", write_string(&map.metadata.name)));
+    lines.push(format!("author = {}", write_string(&map.metadata.author)));
+    lines.push(format!(
+        "description = {}",
+        write_string(&map.metadata.description)
+    ));
+    lines.push(format!("players = {}", map.metadata.players));
+
+    lines.push("[settings]".to_string());
+    if let Some(day_limit) = map.settings.day_limit {
+        lines.push(format!("day_limit = {day_limit}"));
+    }
+    lines.push(format!("fog = {}", map.settings.fog));
+    lines.push(format!("weather = \"{}\"", map.settings.weather.name()));
+
+    lines.push("[players]".to_string());
+    for player in map.players.iter() {
+        lines.push(format!(
+            "{} = {{team={}, color=\"{}\", funds={}}}",
+            player.id,
+            player.team,
+            player.color.name(),
+            player.funds
+        ));
+    }
+
+    write_cells(&mut lines, map, (write_v2_building, write_v2_unit));
+    lines.push(String::new());
+
+    lines.join("\n")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::board::diagnostics::MapDiagnostic;
 
     #[test]
     fn test_basic_map() {
@@ -515,10 +1531,8 @@ mod tests {
                 owner: Owner(1),
                 health: Life(100),
                 unit_type: UnitType::Infantry,
-                movement: Movement{
-                    mov_type: MovementType::Foot,
-                    movements: 30,
-                }
+                ammo_used: 0,
+                fuel_used: 0,
             })
         );
         assert_eq!(
@@ -527,11 +1541,369 @@ mod tests {
                 owner: Owner(2),
                 health: Life(50),
                 unit_type: UnitType::Mech,
-                movement: Movement {
-                    mov_type: MovementType::Foot,
-                    movements: 30,
-                }
+                ammo_used: 0,
+                fuel_used: 0,
             })
         );
     }
+
+    #[test]
+    fn test_write_map() {
+        let mut cells = Matrix::new(2, 1, MapCell::default());
+        cells[(1, 0)] = MapCell {
+            terrain: Terrain::Road,
+            building: Some(Building {
+                owner: Owner(1),
+                income: None,
+                build_type: BuildingType::Headquarters,
+            }),
+            unit: Some(Unit {
+                owner: Owner(2),
+                health: Life(50),
+                unit_type: UnitType::Mech,
+                ammo_used: 0,
+                fuel_used: 0,
+            }),
+        };
+
+        let content = write_map(&Map::from_cells(cells));
+        assert_eq!(
+            content,
+            "version=1
+width=2
+height=1
+terrain = [
+    [\"p\", \"r\"],
+]
+[buildings]
+2x1 = {type=\"headquarters\", owner=1}
+[units]
+2x1 = {type=\"mech\", owner=2, life=50}
+"
+        );
+    }
+
+    #[test]
+    fn test_write_map_round_trip() {
+        let map = parse_map(include_str!("../../assets/map_test.abwm")).unwrap();
+
+        let content = write_map(&map);
+        let parsed = parse_map(&content);
+        assert!(parsed.is_ok(), "{content}");
+        assert_eq!(parsed.unwrap(), map);
+    }
+
+    #[test]
+    fn test_upgrade_v1_map() {
+        let data = "version=1
+            width=2
+            height=1
+            terrain = [[\"p\", \"p\"]]
+            [buildings]
+            1x1 = {type=\"city\", owner=3}
+            2x1 = {type=\"city\"}
+            [units]
+            2x1 = {type=\"infantry\", owner=1}
+            ";
+
+        let map = parse_map(data).unwrap();
+        assert_eq!(map.players, vec![PlayerSlot::new(1), PlayerSlot::new(3)]);
+        assert_eq!(map.players[1].color, PlayerColor::Green);
+        assert_eq!(map.metadata.players, 2);
+        assert_eq!(map.metadata.name, "");
+        assert_eq!(map.settings, MapSettings::default());
+    }
+
+    #[test]
+    fn test_v2_map() {
+        let data = "version=2
+            width=2
+            height=1
+            terrain = [[\"p\", \"r\"]]
+            [metadata]
+            name = \"Duel\"
+            author = \"Dracks\"
+            description = \"Two players\"
+            players = 2
+            [settings]
+            day_limit = 20
+            fog = true
+            weather = \"snow\"
+            [players]
+            2 = {team=1, color=\"black\", funds=500}
+            1 = {team=1}
+            [buildings]
+            1x1 = {type=\"city\", owner=1, income=2000}
+            ";
+
+        let map = parse_map(data).unwrap();
+        assert_eq!(map.metadata.name, "Duel");
+        assert_eq!(map.metadata.author, "Dracks");
+        assert_eq!(map.metadata.description, "Two players");
+        assert_eq!(map.metadata.players, 2);
+        assert_eq!(
+            map.settings,
+            MapSettings {
+                day_limit: Some(20),
+                fog: true,
+                weather: Weather::Snow,
+            }
+        );
+        assert_eq!(
+            map.players,
+            vec![
+                PlayerSlot::new(1),
+                PlayerSlot {
+                    id: 2,
+                    team: 1,
+                    color: PlayerColor::Black,
+                    funds: 500,
+                },
+            ]
+        );
+        assert_eq!(
+            map.cells[(0, 0)].building.unwrap().income,
+            Some(Income(2000))
+        );
+    }
+
+    #[test]
+    fn test_v2_invalid_settings() {
+        let data = "version=2
+            width=1
+            height=1
+            terrain = [[\"p\"]]
+            [settings]
+            weather = \"hail\"
+            ";
+
+        assert!(parse_map(data).is_err());
+    }
+
+    #[test]
+    fn test_write_map_v2_round_trip() {
+        let mut map = parse_map(include_str!("../../assets/map_test.abwm")).unwrap();
+        map.metadata.name = "Test \"map\"".into();
+        map.metadata.description = "Multi\nline".into();
+        map.settings.day_limit = Some(15);
+        map.players[0].funds = 3000;
+        map.cells[(0, 0)].building.as_mut().unwrap().income = Some(Income(500));
+
+        let content = write_map_v2(&map);
+        let parsed = parse_map(&content);
+        assert!(parsed.is_ok(), "{content}");
+        assert_eq!(parsed.unwrap(), map);
+    }
+
+    #[test]
+    fn test_unit_supplies_round_trip() {
+        let data = "version=2
+            width=2
+            height=1
+            terrain = [[\"p\", \"p\"]]
+            [units]
+            1x1 = {type=\"tank\", owner=1, ammo_used=3, fuel_used=12}
+            2x1 = {type=\"infantry\", owner=1}";
+
+        let map = parse_map(data).unwrap();
+        let tank = map.cells[(0, 0)].unit.unwrap();
+        assert_eq!((tank.ammo_used, tank.fuel_used), (3, 12));
+        let infantry = map.cells[(1, 0)].unit.unwrap();
+        assert_eq!((infantry.ammo_used, infantry.fuel_used), (0, 0));
+        assert_eq!(parse_map(&write_map_v2(&map)).unwrap(), map);
+
+        assert_eq!(
+            diagnostics(&data.replace("fuel_used=12", "fuel_used=-1"))[0].location,
+            Some(MapLocation::Unit("1x1".into()))
+        );
+    }
+
+    fn diagnostics(data: &str) -> Vec<MapDiagnostic> {
+        match parse_map(data) {
+            Err(MapLoaderError::Invalid(diagnostics)) => diagnostics.0,
+            other => panic!("Expected an invalid map, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_syntax_error_location() {
+        let data = "version=1\nwidth=1\nheight=\n";
+
+        let errors = diagnostics(data);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].location,
+            Some(MapLocation::File { line: 3, column: 8 })
+        );
+    }
+
+    #[test]
+    fn test_invalid_positions_do_not_panic() {
+        let data = "version=1
+            width=1
+            height=1
+            terrain = [[\"p\"]]
+            [units]
+            ax1 = {type=\"infantry\", owner=1}
+            2x1 = {type=\"infantry\", owner=1}
+            [buildings]
+            1 = {type=\"city\"}
+            ";
+
+        let locations: Vec<_> = diagnostics(data)
+            .into_iter()
+            .filter_map(|diagnostic| diagnostic.location)
+            .collect();
+        assert_eq!(
+            locations,
+            vec![
+                MapLocation::Unit("2x1".into()),
+                MapLocation::Unit("ax1".into()),
+                MapLocation::Building("1".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collects_all_errors() {
+        let data = "version=2
+            width=2
+            height=2
+            terrain = [
+                [\"p\", \"x\"],
+                [\"p\"],
+            ]
+            [settings]
+            fog = 1
+            [players]
+            0 = {}
+            [units]
+            1x1 = {type=\"dragon\", owner=0}
+            [buildings]
+            1x2 = {type=\"city\", income=-5}
+            ";
+
+        let locations: Vec<_> = diagnostics(data)
+            .into_iter()
+            .filter_map(|diagnostic| diagnostic.location)
+            .collect();
+        assert_eq!(
+            locations,
+            vec![
+                MapLocation::Terrain { row: 1, column: 2 },
+                MapLocation::TerrainRow(2),
+                MapLocation::Unit("1x1".into()),
+                MapLocation::Unit("1x1".into()),
+                MapLocation::Building("1x2".into()),
+                MapLocation::Player("0".into()),
+                MapLocation::Property("settings.fog".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_structures_terrain() {
+        let data = "version=1
+            width=5
+            height=1
+            terrain = [[\"b\", \"rv\", \"w\", \"bw\", \"bw:50\"]]";
+
+        let map = parse_map(data).unwrap();
+        let terrain: Vec<_> = map.cells.iter().map(|cell| cell.terrain).collect();
+        assert_eq!(
+            terrain,
+            vec![
+                Terrain::Bridge,
+                Terrain::River,
+                Terrain::Wall,
+                Terrain::BreakableWall(Life(100)),
+                Terrain::BreakableWall(Life(50)),
+            ]
+        );
+        assert_eq!(parse_map(&write_map_v2(&map)).unwrap(), map);
+    }
+
+    #[test]
+    fn test_breakable_wall_damage() {
+        let wall = Terrain::BreakableWall(Life::new());
+        assert_eq!(wall.damage(30), Terrain::BreakableWall(Life(70)));
+        assert_eq!(wall.damage(30).code(), "bw:70");
+        assert_eq!(
+            Terrain::try_from("bw:70").ok(),
+            Some(Terrain::BreakableWall(Life(70)))
+        );
+        assert!(Terrain::try_from("bw:0").is_err());
+        assert!(Terrain::try_from("bw:101").is_err());
+
+        let data = "version=1
+            width=2
+            height=1
+            terrain = [[\"bw:70\", \"bw:20\"]]";
+        let map = parse_map(data).unwrap();
+        assert_eq!(map.cells[(1, 0)].terrain, Terrain::BreakableWall(Life(20)));
+        assert_eq!(parse_map(&write_map(&map)).unwrap(), map);
+        assert_eq!(parse_map(&write_map_v2(&map)).unwrap(), map);
+        assert_eq!(wall.damage(30).damage(70), Terrain::Plane);
+        assert_eq!(Terrain::Wall.damage(100), Terrain::Wall);
+    }
+
+    #[test]
+    fn test_building_roster() {
+        let data = "version=2
+            width=3
+            height=2
+            terrain = [[\"p\", \"p\", \"s\"], [\"p\", \"p\", \"p\"]]
+            [buildings]
+            1x1 = {type=\"town\"}
+            2x1 = {type=\"port\", owner=1}
+            3x1 = {type=\"oilrig\"}
+            1x2 = {type=\"airport\", income=1500}
+            2x2 = {type=\"lab\"}
+            3x2 = {type=\"comtower\", owner=2}";
+
+        let map = parse_map(data).unwrap();
+        let town = map.cells[(0, 0)].building.unwrap();
+        assert_eq!(town.build_type, BuildingType::Town);
+        assert_eq!(town.income, None);
+        assert_eq!(
+            map.cells[(0, 1)].building.unwrap().income,
+            Some(Income(1500))
+        );
+        let rules = Rules::builtin();
+        assert_eq!(rules.building(BuildingType::Town).income, Income(500));
+        assert_eq!(rules.building(BuildingType::Lab).income, Income(0));
+        assert_eq!(rules.building(BuildingType::Port).builds, &[UnitClass::Sea]);
+        assert_eq!(
+            rules.building(BuildingType::City).repairs,
+            &[UnitClass::Land]
+        );
+        assert!(rules.building(BuildingType::ComTower).builds.is_empty());
+
+        assert_eq!(parse_map(&write_map_v2(&map)).unwrap(), map);
+    }
+
+    #[test]
+    fn test_unit_roster() {
+        for unit_type in UnitType::ALL {
+            assert_eq!(UnitType::try_from(unit_type.name()).ok(), Some(unit_type));
+        }
+
+        let data = "version=1
+            width=2
+            height=1
+            terrain = [[\"p\", \"s\"]]
+            [units]
+            1x1 = {type=\"Artillery\", owner=1}
+            2x1 = {type=\"sub\", owner=2}";
+        let map = parse_map(data).unwrap();
+        let artillery = map.cells[(0, 0)].unit.unwrap();
+        assert_eq!(artillery.unit_type, UnitType::Artillery);
+        assert_eq!(
+            Rules::builtin().unit(artillery.unit_type).attack_range,
+            Some(AttackRange { min: 2, max: 3 })
+        );
+        let sub = map.cells[(1, 0)].unit.unwrap();
+        assert_eq!(sub.unit_type, UnitType::Sub);
+        assert_eq!(Rules::builtin().unit(sub.unit_type).class(), UnitClass::Sea);
+    }
 }
@@ -1,25 +1,37 @@
 use assets_helper::AssetsTrait;
 use bevy::prelude::*;
 
+mod actions;
 mod board;
+mod combat;
+mod diagnostics;
 mod direction;
+mod fog;
 mod map;
+mod movement;
+mod preview;
+mod rules;
 mod terrain;
+#[cfg(test)]
+mod test_utils;
+mod validator;
+mod victory;
 
 use bevy_flair::style::components::NodeStyleSheet;
+pub use actions::*;
 pub use board::*;
+pub use combat::*;
+pub use diagnostics::*;
+pub use direction::*;
 pub use map::*;
+pub use movement::*;
+pub use preview::MapPreview;
+pub use rules::*;
 use ui_helpers::prelude::*;
-pub use direction::*;
+pub use validator::validate_map;
+pub use victory::*;
 
-use crate::{
-    assets::FileAssets,
-    board::{
-        board::{Tiler, center_camera, drop_terrain},
-        map::{Map, MapAssetLoader},
-        terrain::build_auto_tiler,
-    },
-};
+use crate::{assets::FileAssets, board::terrain::build_auto_tiler};
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub struct ShowBoard;
@@ -42,12 +54,21 @@ impl Plugin for BoardPlugin {
                 OnEnter(BoardLoad::Complete),
                 (Board::spawn_terrain, center_camera).chain(),
             )
-            .add_systems(OnExit(ShowBoard), drop_terrain);
+            .add_systems(OnEnter(ShowBoard), seed_combat_rng)
+            .add_systems(OnExit(ShowBoard), drop_terrain)
+            .add_message::<TerrainChanged>()
+            .add_message::<BuildingCaptured>()
+            .add_systems(
+                Update,
+                (refresh_terrain, refresh_buildings).run_if(in_state(BoardLoad::Complete)),
+            );
 
         // app.insert_resource(base_board());
 
         app.init_asset::<Map>()
-            .init_asset_loader::<MapAssetLoader>();
+            .init_asset_loader::<MapAssetLoader>()
+            .init_asset::<Rules>()
+            .init_asset_loader::<RulesAssetLoader>();
 
         app.add_sub_state::<BoardLoad>()
             .add_plugins(LoadingPlugin::<BoardLoad>::new())
@@ -60,8 +81,10 @@ impl Plugin for BoardPlugin {
 }
 
 fn spawn_loading(mut commands: Commands, assets: Res<AssetServer>) {
-    let loading = LoadFiles::from_duration(0.1)
-        .with_assets(vec![FileAssets::MapTestAbwm.load::<Map>(&assets).into()]);
+    let loading = LoadFiles::from_duration(0.1).with_assets(vec![
+        FileAssets::MapTestAbwm.load::<Map>(&assets).into(),
+        FileAssets::DefaultRulesToml.load::<Rules>(&assets).into(),
+    ]);
 
     commands.insert_resource(loading);
     commands.spawn((
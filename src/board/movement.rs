@@ -0,0 +1,527 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use bevy::math::{UVec2, uvec2};
+
+use crate::{
+    board::{board::Board, direction::Direction, map::Unit},
+    interactive::MovementType,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PossibleMovement {
+    pub position: UVec2,
+    /// Cells walked from the unit following the cheapest path
+    pub layer: u32,
+    /// Movement points spent to get here following the cheapest path
+    pub cost: u32,
+    /// Cell before this one in the cheapest path, None for the unit position
+    pub previous: Option<UVec2>,
+    /// False when an ally is there, the unit can only go through it
+    pub can_stop: bool,
+}
+
+/// Cells a unit can reach with its movement points and fuel, with the cheapest way
+/// to get to each of them
+#[derive(Debug)]
+pub struct ReachableCells {
+    origin: UVec2,
+    budget: u32,
+    /// Cells the unit can move before running out of fuel
+    fuel: u32,
+    mov_type: MovementType,
+    cells: HashMap<UVec2, PossibleMovement>,
+}
+
+/// Cells a unit goes through to get to its destination, the first one is the unit
+/// position
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MovementPath {
+    cells: Vec<UVec2>,
+    cost: u32,
+}
+
+impl MovementPath {
+    pub fn new(origin: UVec2) -> Self {
+        Self {
+            cells: vec![origin],
+            cost: 0,
+        }
+    }
+
+    pub fn cells(&self) -> &[UVec2] {
+        &self.cells
+    }
+
+    pub fn cost(&self) -> u32 {
+        self.cost
+    }
+
+    pub fn destination(&self) -> UVec2 {
+        *self.cells.last().expect("A path always has the origin")
+    }
+
+    /// Cuts the path in front of the first enemy in it, the unit falls in an ambush
+    /// and stops at the last cell without units. Returns the position of the enemy.
+    pub fn stop_at_enemies(&mut self, unit: &Unit, board: &Board) -> Option<UVec2> {
+        let idx = self.cells.iter().position(|cell| {
+            board
+                .units
+                .get(cell)
+                .is_some_and(|other| other.owner != unit.owner)
+        })?;
+        let enemy = self.cells[idx];
+        let stop = self.cells[1..idx]
+            .iter()
+            .rposition(|cell| !board.units.contains_key(cell))
+            .map_or(0, |stop| stop + 1);
+        self.cells.truncate(stop + 1);
+        self.cost = self.cells[1..]
+            .iter()
+            .filter_map(|cell| {
+                board.rules().movement_cost(
+                    board.rules().unit(unit.unit_type).mov_type,
+                    board.get(cell)?,
+                )
+            })
+            .sum();
+        Some(enemy)
+    }
+}
+
+impl ReachableCells {
+    pub fn origin(&self) -> UVec2 {
+        self.origin
+    }
+
+    pub fn get(&self, position: &UVec2) -> Option<&PossibleMovement> {
+        self.cells.get(position)
+    }
+
+    pub fn contains(&self, position: &UVec2) -> bool {
+        self.cells.contains_key(position)
+    }
+
+    /// The unit can end its movement in the position
+    pub fn can_stop(&self, position: &UVec2) -> bool {
+        self.cells.get(position).is_some_and(|cell| cell.can_stop)
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PossibleMovement> {
+        self.cells.values()
+    }
+
+    /// Cheapest path from the unit to the target, both included
+    pub fn path_to(&self, target: UVec2) -> Option<Vec<UVec2>> {
+        let mut path = vec![target];
+        let mut current = self.cells.get(&target)?;
+        while let Some(previous) = current.previous {
+            path.push(previous);
+            current = &self.cells[&previous];
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// Cheapest movement path from the unit to the target
+    pub fn movement_path(&self, target: UVec2) -> Option<MovementPath> {
+        Some(MovementPath {
+            cost: self.cells.get(&target)?.cost,
+            cells: self.path_to(target)?,
+        })
+    }
+
+    /// Moves the end of the path to the target. The path follows the player when the
+    /// target is next to its end and still within the movement points, going back to
+    /// an already visited cell cuts the path there and any other reachable target
+    /// uses the cheapest path. Returns if the path has changed.
+    pub fn trace(&self, path: &mut MovementPath, target: UVec2, board: &Board) -> bool {
+        if path.destination() == target || !self.contains(&target) {
+            return false;
+        }
+        if let Some(idx) = path.cells.iter().position(|cell| *cell == target) {
+            path.cells.truncate(idx + 1);
+            path.cost = path.cells[1..]
+                .iter()
+                .filter_map(|cell| self.step_cost(cell, board))
+                .sum();
+            return true;
+        }
+        if Direction::between(&path.destination(), &target).is_some()
+            && let Some(cost) = self.step_cost(&target, board)
+            && path.cost + cost <= self.budget
+            && path.cells.len() as u32 <= self.fuel
+        {
+            path.cells.push(target);
+            path.cost += cost;
+            return true;
+        }
+        match self.movement_path(target) {
+            Some(cheapest) => {
+                *path = cheapest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn step_cost(&self, position: &UVec2, board: &Board) -> Option<u32> {
+        board
+            .rules()
+            .movement_cost(self.mov_type, board.get(position)?)
+    }
+}
+
+impl IntoIterator for ReachableCells {
+    type Item = PossibleMovement;
+    type IntoIter = std::collections::hash_map::IntoValues<UVec2, PossibleMovement>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.cells.into_values()
+    }
+}
+
+impl Unit {
+    /// Dijkstra over the board from the position of the unit, a cell is reachable
+    /// when the cost of the cheapest path is within the movement points and the
+    /// unit has fuel for its cells
+    pub fn get_movements(&self, pos: UVec2, board: &Board) -> ReachableCells {
+        self.get_movements_seeing(pos, board, |_| true)
+    }
+
+    /// Same as [`Unit::get_movements`] but only the enemies in visible cells are
+    /// known. With ambush rules the hidden enemies don't block the paths.
+    pub fn get_movements_seeing(
+        &self,
+        pos: UVec2,
+        board: &Board,
+        is_visible: impl Fn(&UVec2) -> bool,
+    ) -> ReachableCells {
+        let pathfinding = board.rules().pathfinding();
+        let stats = board.rules().unit(self.unit_type);
+        let total_movement = stats.movement;
+        let fuel = board.fuel_left(self);
+        let mut cells: HashMap<UVec2, PossibleMovement> = HashMap::new();
+        let mut pending = BinaryHeap::new();
+
+        cells.insert(
+            pos,
+            PossibleMovement {
+                position: pos,
+                layer: 0,
+                cost: 0,
+                previous: None,
+                can_stop: true,
+            },
+        );
+        pending.push(Reverse((0, 0, pos.x, pos.y)));
+
+        while let Some(Reverse((cost, layer, x, y))) = pending.pop() {
+            let position = uvec2(x, y);
+            if cells
+                .get(&position)
+                .is_some_and(|best| (best.cost, best.layer) < (cost, layer))
+            {
+                continue;
+            }
+            for dir in Direction::ADJACENT {
+                let Some(new_pos) = dir.move_point(&position) else {
+                    continue;
+                };
+                let Some(terrain) = board.get(&new_pos) else {
+                    continue;
+                };
+                let Some(move_cost) = board.rules().movement_cost(stats.mov_type, terrain) else {
+                    continue;
+                };
+                let new_cost = cost + move_cost;
+                if new_cost > total_movement || layer + 1 > fuel {
+                    continue;
+                }
+                let can_stop = match board.units.get(&new_pos) {
+                    Some(other) if other.owner == self.owner => {
+                        if !pathfinding.pass_through_allies {
+                            continue;
+                        }
+                        board.can_load(&pos, &new_pos) || board.can_join(&pos, &new_pos)
+                    }
+                    Some(_) if is_visible(&new_pos) || !pathfinding.ambush => continue,
+                    _ => true,
+                };
+                let candidate = PossibleMovement {
+                    position: new_pos,
+                    layer: layer + 1,
+                    cost: new_cost,
+                    previous: Some(position),
+                    can_stop,
+                };
+                let is_better = match cells.get(&new_pos) {
+                    Some(existing) => {
+                        (candidate.cost, candidate.layer) < (existing.cost, existing.layer)
+                    }
+                    None => true,
+                };
+                if is_better {
+                    cells.insert(new_pos, candidate);
+                    pending.push(Reverse((new_cost, layer + 1, new_pos.x, new_pos.y)));
+                }
+            }
+        }
+
+        ReachableCells {
+            origin: pos,
+            budget: total_movement,
+            fuel,
+            mov_type: stats.mov_type,
+            cells,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{
+        map::UnitType,
+        rules::{Rules, parse_rules},
+        test_utils::{grid_map, make_board, make_board_with_rules, row_map},
+    };
+
+    /// Infantry at 1x1 whose movements are checked
+    const INFANTRY: &str = "1x1 = {type=\"infantry\", owner=1}";
+
+    #[test]
+    fn test_budget_is_inclusive() {
+        let board = make_board(&row_map(&["p"; 6], "", INFANTRY, ""));
+        let unit = board.units[&UVec2::ZERO];
+
+        let movements = unit.get_movements(UVec2::ZERO, &board);
+        assert_eq!(movements.len(), 4);
+        assert_eq!(movements.get(&uvec2(3, 0)).unwrap().cost, 30);
+        assert!(!movements.contains(&uvec2(4, 0)));
+    }
+
+    #[test]
+    fn test_fuel_limits_movement() {
+        let mut board = make_board(&row_map(&["p"; 6], "", INFANTRY, ""));
+        let fuel = board.rules().unit(UnitType::Infantry).fuel;
+        board.burn_fuel(UVec2::ZERO, fuel - 1);
+        let unit = board.units[&UVec2::ZERO];
+
+        let movements = unit.get_movements(UVec2::ZERO, &board);
+        assert_eq!(movements.len(), 2);
+        let mut path = MovementPath::new(UVec2::ZERO);
+        assert!(movements.trace(&mut path, uvec2(1, 0), &board));
+        assert!(!movements.trace(&mut path, uvec2(2, 0), &board));
+    }
+
+    #[test]
+    fn test_cheapest_path_around_mountains() {
+        // Climbing the mountain costs 30 while going around it over the plain costs 40
+        let board = make_board(&grid_map(&[&["p", "m", "p"], &["p"; 3]], "", INFANTRY, ""));
+        let unit = board.units[&UVec2::ZERO];
+
+        let movements = unit.get_movements(UVec2::ZERO, &board);
+        let target = movements.get(&uvec2(2, 0)).unwrap();
+        assert_eq!(target.cost, 30);
+        assert_eq!(target.layer, 2);
+        assert_eq!(
+            movements.path_to(uvec2(2, 0)),
+            Some(vec![uvec2(0, 0), uvec2(1, 0), uvec2(2, 0)])
+        );
+
+        let board = make_board(&grid_map(
+            &[&["p", "m", "m", "p"], &["p"; 4]],
+            "",
+            INFANTRY,
+            "",
+        ));
+        let unit = board.units[&UVec2::ZERO];
+        let movements = unit.get_movements(UVec2::ZERO, &board);
+        assert_eq!(movements.get(&uvec2(3, 0)), None);
+        assert_eq!(
+            movements.path_to(uvec2(2, 1)),
+            Some(vec![uvec2(0, 0), uvec2(0, 1), uvec2(1, 1), uvec2(2, 1)])
+        );
+    }
+
+    #[test]
+    fn test_impassable_terrain() {
+        let board = make_board(&grid_map(&[&["p", "s", "p"], &["p"; 3]], "", INFANTRY, ""));
+        let unit = board.units[&UVec2::ZERO];
+
+        let movements = unit.get_movements(UVec2::ZERO, &board);
+        assert!(!movements.contains(&uvec2(1, 0)));
+        assert_eq!(
+            movements.path_to(uvec2(2, 0)),
+            None,
+            "Going around the sea costs 40"
+        );
+        assert_eq!(movements.get(&uvec2(1, 1)).unwrap().cost, 20);
+    }
+
+    #[test]
+    fn test_origin() {
+        let board = make_board(&row_map(&["p"], "", INFANTRY, ""));
+        let unit = board.units[&UVec2::ZERO];
+
+        let movements = unit.get_movements(UVec2::ZERO, &board);
+        assert_eq!(
+            movements.iter().collect::<Vec<_>>(),
+            vec![&PossibleMovement {
+                position: UVec2::ZERO,
+                layer: 0,
+                cost: 0,
+                previous: None,
+                can_stop: true,
+            }]
+        );
+        assert_eq!(movements.path_to(UVec2::ZERO), Some(vec![UVec2::ZERO]));
+    }
+
+    #[test]
+    fn test_trace_custom_path() {
+        let board = make_board(&grid_map(&[&["p"; 4], &["p"; 4]], "", INFANTRY, ""));
+        let unit = board.units[&UVec2::ZERO];
+        let movements = unit.get_movements(UVec2::ZERO, &board);
+        let mut path = MovementPath::new(movements.origin());
+
+        assert!(movements.trace(&mut path, uvec2(0, 1), &board));
+        assert!(movements.trace(&mut path, uvec2(1, 1), &board));
+        assert!(movements.trace(&mut path, uvec2(1, 0), &board));
+        assert_eq!(
+            path.cells(),
+            &[uvec2(0, 0), uvec2(0, 1), uvec2(1, 1), uvec2(1, 0)]
+        );
+        assert_eq!(path.cost(), 30);
+
+        // Out of movement points, the cheapest path is used instead
+        assert!(movements.trace(&mut path, uvec2(2, 0), &board));
+        assert_eq!(path.cells(), &[uvec2(0, 0), uvec2(1, 0), uvec2(2, 0)]);
+        assert_eq!(path.cost(), 20);
+
+        // Going back cuts the path
+        assert!(movements.trace(&mut path, uvec2(1, 0), &board));
+        assert_eq!(path.cells(), &[uvec2(0, 0), uvec2(1, 0)]);
+        assert_eq!(path.cost(), 10);
+
+        // Unreachable cells keep the path
+        assert!(!movements.trace(&mut path, uvec2(3, 1), &board));
+        assert!(!movements.trace(&mut path, uvec2(1, 0), &board));
+        assert_eq!(path.cells(), &[uvec2(0, 0), uvec2(1, 0)]);
+    }
+
+    fn pathfinding_rules(pass_through_allies: bool, ambush: bool) -> Rules {
+        let content = include_str!("../../assets/default.rules.toml")
+            .replace(
+                "pass_through_allies = true",
+                &format!("pass_through_allies = {pass_through_allies}"),
+            )
+            .replace("ambush = true", &format!("ambush = {ambush}"));
+        parse_rules(&content).unwrap()
+    }
+
+    #[test]
+    fn test_pass_through_allies() {
+        let board = make_board(&row_map(
+            &["p"; 4],
+            "",
+            "1x1 = {type=\"infantry\", owner=1}
+            2x1 = {type=\"infantry\", owner=1}",
+            "",
+        ));
+        let unit = board.units[&UVec2::ZERO];
+
+        let movements = unit.get_movements(UVec2::ZERO, &board);
+        assert!(movements.contains(&uvec2(1, 0)));
+        assert!(!movements.can_stop(&uvec2(1, 0)));
+        assert!(movements.can_stop(&uvec2(2, 0)));
+        assert!(movements.can_stop(&UVec2::ZERO));
+
+        let board = make_board_with_rules(
+            &row_map(
+                &["p"; 4],
+                "",
+                "1x1 = {type=\"infantry\", owner=1}
+                2x1 = {type=\"infantry\", owner=1}",
+                "",
+            ),
+            pathfinding_rules(false, true),
+        );
+        let unit = board.units[&UVec2::ZERO];
+        let movements = unit.get_movements(UVec2::ZERO, &board);
+        assert_eq!(movements.len(), 1);
+    }
+
+    #[test]
+    fn test_enemies_block() {
+        let board = make_board(&grid_map(
+            &[&["p"; 4], &["p"; 4]],
+            "",
+            "1x1 = {type=\"infantry\", owner=1}
+            2x1 = {type=\"infantry\", owner=2}",
+            "",
+        ));
+        let unit = board.units[&UVec2::ZERO];
+
+        let movements = unit.get_movements(UVec2::ZERO, &board);
+        assert!(!movements.contains(&uvec2(1, 0)));
+        assert_eq!(
+            movements.path_to(uvec2(2, 0)),
+            None,
+            "Going around the enemy costs 40"
+        );
+        assert!(movements.can_stop(&uvec2(1, 1)));
+    }
+
+    #[test]
+    fn test_ambush() {
+        let board = make_board(&row_map(
+            &["p"; 4],
+            "",
+            "1x1 = {type=\"infantry\", owner=1}
+            2x1 = {type=\"infantry\", owner=1}
+            3x1 = {type=\"infantry\", owner=2}",
+            "",
+        ));
+        let unit = board.units[&UVec2::ZERO];
+        let hidden = uvec2(2, 0);
+
+        let movements = unit.get_movements_seeing(UVec2::ZERO, &board, |pos| *pos != hidden);
+        let mut path = movements.movement_path(uvec2(3, 0)).unwrap();
+        assert_eq!(path.cost(), 30);
+        assert_eq!(path.stop_at_enemies(&unit, &board), Some(hidden));
+        assert_eq!(path.cells(), &[UVec2::ZERO], "The ally cell is skipped");
+        assert_eq!(path.cost(), 0);
+
+        let movements = unit.get_movements_seeing(UVec2::ZERO, &board, |_| true);
+        assert!(!movements.contains(&hidden));
+
+        let board = make_board_with_rules(
+            &row_map(
+                &["p"; 4],
+                "",
+                "1x1 = {type=\"infantry\", owner=1}
+                3x1 = {type=\"infantry\", owner=2}",
+                "",
+            ),
+            pathfinding_rules(true, false),
+        );
+        let unit = board.units[&UVec2::ZERO];
+        let movements = unit.get_movements_seeing(UVec2::ZERO, &board, |pos| *pos != hidden);
+        assert!(!movements.contains(&hidden));
+        let mut path = movements.movement_path(uvec2(1, 0)).unwrap();
+        assert_eq!(path.stop_at_enemies(&unit, &board), None);
+        assert_eq!(path.cells(), &[UVec2::ZERO, uvec2(1, 0)]);
+    }
+}
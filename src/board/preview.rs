@@ -0,0 +1,201 @@
+use std::{collections::HashMap, path::Path};
+
+use assets_helper::AssetsTrait;
+use auto_tiler::AutoTiler;
+use bevy::math::{UVec2, uvec2};
+use image::{RgbaImage, imageops};
+
+use crate::{
+    assets::FileAssets,
+    board::{
+        board::Board,
+        map::{Map, UnitType},
+        terrain::{TileTerrain, build_auto_tiler},
+    },
+};
+
+const TILE_SIZE: u32 = 32;
+
+/// Draws maps into images without a window or a GPU, using the same tiles as the
+/// board in game
+pub struct MapPreview {
+    auto_tiler: AutoTiler<TileTerrain, UVec2>,
+    terrain: RgbaImage,
+    units: HashMap<UnitType, RgbaImage>,
+}
+
+impl MapPreview {
+    /// Units without a sprite sheet are not drawn
+    pub fn new(terrain: RgbaImage, units: HashMap<UnitType, RgbaImage>) -> Self {
+        Self {
+            auto_tiler: build_auto_tiler(),
+            terrain,
+            units,
+        }
+    }
+
+    /// Loads the sprite sheets from the assets folder
+    pub fn load(assets_dir: &Path) -> Result<Self, image::ImageError> {
+        let open = |asset: FileAssets| -> Result<RgbaImage, image::ImageError> {
+            Ok(image::open(assets_dir.join(asset.path()))?.into_rgba8())
+        };
+        let units = UnitType::ALL
+            .into_iter()
+            .map(|unit_type| Ok((unit_type, open(unit_type.sprite())?)))
+            .collect::<Result<_, image::ImageError>>()?;
+        Ok(Self::new(open(FileAssets::ImagesGameTerrainPng)?, units))
+    }
+
+    fn draw(target: &mut RgbaImage, sheet: &RgbaImage, tile: UVec2, pos: UVec2, height: u32) {
+        let sprite = imageops::crop_imm(
+            sheet,
+            tile.x * TILE_SIZE,
+            tile.y * TILE_SIZE,
+            TILE_SIZE,
+            TILE_SIZE,
+        );
+        // The board grows upwards, the images downwards
+        imageops::overlay(
+            target,
+            &*sprite,
+            (pos.x * TILE_SIZE) as i64,
+            ((height - 1 - pos.y) * TILE_SIZE) as i64,
+        );
+    }
+
+    /// Renders the map at full size, 32 pixels per cell
+    pub fn render(&self, map: &Map) -> RgbaImage {
+        let board = Board::new(map.clone());
+        let (width, height) = map.get_size();
+        let (width, height) = (width as u32, height as u32);
+        let mut image = RgbaImage::new(width * TILE_SIZE, height * TILE_SIZE);
+
+        for layer in 0..board.layers_len() {
+            for (x, y) in map.cells.keys() {
+                let pos = uvec2(x as u32, y as u32);
+                if let Some(tile) = board.get_tile(&self.auto_tiler, layer, pos) {
+                    Self::draw(&mut image, &self.terrain, tile, pos, height);
+                }
+            }
+        }
+
+        for (x, y) in map.cells.keys() {
+            let pos = uvec2(x as u32, y as u32);
+            let cell = &map.cells[(x, y)];
+            if let Some(building) = &cell.building {
+                Self::draw(
+                    &mut image,
+                    &self.terrain,
+                    building.build_type.tile(),
+                    pos,
+                    height,
+                );
+                if pos.y + 1 < height {
+                    Self::draw(
+                        &mut image,
+                        &self.terrain,
+                        building.build_type.top_tile(),
+                        pos + UVec2::Y,
+                        height,
+                    );
+                }
+            }
+            if let Some(sheet) = cell.unit.and_then(|unit| self.units.get(&unit.unit_type)) {
+                Self::draw(&mut image, sheet, UVec2::ZERO, pos, height);
+            }
+        }
+
+        image
+    }
+
+    /// Renders the map scaled down to fit in a square of the given size
+    pub fn thumbnail(&self, map: &Map, size: u32) -> RgbaImage {
+        let image = self.render(map);
+        let scale = (size as f32 / image.width().max(image.height()) as f32).min(1.0);
+        let width = ((image.width() as f32 * scale) as u32).max(1);
+        let height = ((image.height() as f32 * scale) as u32).max(1);
+        imageops::thumbnail(&image, width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::map::parse_map;
+    use image::Rgba;
+
+    /// Sheet where every pixel encodes the coordinates of its tile
+    fn coords_sheet(cols: u32, rows: u32) -> RgbaImage {
+        RgbaImage::from_fn(cols * TILE_SIZE, rows * TILE_SIZE, |x, y| {
+            Rgba([(x / TILE_SIZE) as u8, (y / TILE_SIZE) as u8, 0, 255])
+        })
+    }
+
+    fn preview() -> MapPreview {
+        MapPreview::new(
+            coords_sheet(68, 45),
+            HashMap::from([
+                (
+                    UnitType::Infantry,
+                    RgbaImage::from_pixel(5 * TILE_SIZE, 8 * TILE_SIZE, Rgba([200, 0, 0, 255])),
+                ),
+                (
+                    UnitType::Cruiser,
+                    RgbaImage::from_pixel(TILE_SIZE, TILE_SIZE, Rgba([0, 0, 200, 255])),
+                ),
+            ]),
+        )
+    }
+
+    #[test]
+    fn test_render_terrain() {
+        let map = parse_map(
+            "version=1
+            width=1
+            height=2
+            terrain = [[\"p\"], [\"r\"]]",
+        )
+        .unwrap();
+
+        let image = preview().render(&map);
+        assert_eq!(image.dimensions(), (32, 64));
+        // Road is the second row of the map, so it is on the top of the image
+        assert_eq!(image.get_pixel(0, 0), &Rgba([3, 3, 0, 255]));
+        assert_eq!(image.get_pixel(31, 63), &Rgba([0, 15, 0, 255]));
+    }
+
+    #[test]
+    fn test_render_buildings_and_units() {
+        let map = parse_map(
+            "version=1
+            width=2
+            height=2
+            terrain = [[\"p\", \"p\"], [\"p\", \"s\"]]
+            [buildings]
+            1x1 = {type=\"city\"}
+            [units]
+            2x1 = {type=\"infantry\", owner=1}
+            2x2 = {type=\"cruiser\", owner=2}",
+        )
+        .unwrap();
+
+        let image = preview().render(&map);
+        assert_eq!(image.get_pixel(0, 63), &Rgba([0, 37, 0, 255]));
+        assert_eq!(image.get_pixel(0, 0), &Rgba([0, 36, 0, 255]));
+        assert_eq!(image.get_pixel(32, 63), &Rgba([200, 0, 0, 255]));
+        assert_eq!(image.get_pixel(32, 0), &Rgba([0, 0, 200, 255]));
+    }
+
+    #[test]
+    fn test_thumbnail_size() {
+        let map = parse_map(
+            "version=1
+            width=4
+            height=2
+            terrain = [[\"p\", \"p\", \"p\", \"p\"], [\"p\", \"p\", \"p\", \"p\"]]",
+        )
+        .unwrap();
+
+        assert_eq!(preview().thumbnail(&map, 64).dimensions(), (64, 32));
+    }
+}
@@ -0,0 +1,751 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    prelude::*,
+};
+use thiserror::Error;
+use toml::{Table, Value};
+
+use crate::{
+    board::{
+        combat::Weapon,
+        diagnostics::{MapDiagnostics, MapLocation},
+        map::{AttackRange, BuildingType, Terrain, UnitClass, UnitStats, UnitType},
+    },
+    interactive::{CapturePoints, Income, MovementType},
+};
+
+/// Key of the damage tables for the damage against breakable walls
+const WALL_KEY: &str = "wall";
+
+static BUILTIN_RULES: LazyLock<Rules> = LazyLock::new(|| {
+    parse_rules(include_str!("../../assets/default.rules.toml"))
+        .expect("The default rules should be valid")
+});
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct BuildingStats {
+    pub income: Income,
+    pub capture_points: CapturePoints,
+    /// Defense stars given to the units on the building
+    pub defense: u8,
+    /// Cells the owner sees around the building with fog of war
+    pub vision: u32,
+    pub builds: Vec<UnitClass>,
+    pub repairs: Vec<UnitClass>,
+}
+
+/// How the units get along with other units while moving
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PathfindingRules {
+    /// Units can cross the cells of the units of the same player, but not stop there
+    pub pass_through_allies: bool,
+    /// Hidden enemies don't block the paths, they trap the units crossing them
+    pub ambush: bool,
+}
+
+/// Conditions that end the match, besides the day limit of the map and players
+/// resigning
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct VictoryRules {
+    /// Players lose when they don't own a headquarters anymore
+    pub headquarters: bool,
+    /// Players without units lose, from the second day
+    pub rout: bool,
+    /// Buildings a player needs to own to win
+    pub capture_buildings: Option<u32>,
+}
+
+/// Balance of the game: stats of the units, movement costs, defense of the terrain
+/// and properties of the buildings
+#[derive(Asset, TypePath, Debug, PartialEq, Clone)]
+pub struct Rules {
+    units: HashMap<UnitType, UnitStats>,
+    movement_costs: HashMap<(MovementType, Terrain), u32>,
+    terrain_defense: HashMap<Terrain, u8>,
+    buildings: HashMap<BuildingType, BuildingStats>,
+    pathfinding: PathfindingRules,
+    damage: HashMap<(UnitType, Weapon, UnitType), u32>,
+    wall_damage: HashMap<(UnitType, Weapon), u32>,
+    victory: VictoryRules,
+}
+
+impl Rules {
+    /// Rules shipped with the game, embedded so they are available without the
+    /// asset server
+    pub fn builtin() -> &'static Rules {
+        &BUILTIN_RULES
+    }
+
+    pub fn unit(&self, unit_type: UnitType) -> &UnitStats {
+        &self.units[&unit_type]
+    }
+
+    /// Cost of entering the terrain, None when it can not be crossed
+    pub fn movement_cost(&self, mov_type: MovementType, terrain: &Terrain) -> Option<u32> {
+        self.movement_costs
+            .get(&(mov_type, terrain.kind()))
+            .copied()
+    }
+
+    pub fn defense(&self, terrain: &Terrain) -> u8 {
+        self.terrain_defense
+            .get(&terrain.kind())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn building(&self, building_type: BuildingType) -> &BuildingStats {
+        &self.buildings[&building_type]
+    }
+
+    pub fn pathfinding(&self) -> &PathfindingRules {
+        &self.pathfinding
+    }
+
+    pub fn victory(&self) -> &VictoryRules {
+        &self.victory
+    }
+
+    /// Damage of the weapon against the defender before any modifier, None when the
+    /// weapon can not attack it
+    pub fn base_damage(
+        &self,
+        attacker: UnitType,
+        weapon: Weapon,
+        defender: UnitType,
+    ) -> Option<u32> {
+        self.damage.get(&(attacker, weapon, defender)).copied()
+    }
+
+    /// Damage of the weapon against breakable walls, None when it can not break them
+    pub fn wall_damage(&self, attacker: UnitType, weapon: Weapon) -> Option<u32> {
+        self.wall_damage.get(&(attacker, weapon)).copied()
+    }
+}
+
+#[derive(Default)]
+pub struct RulesAssetLoader;
+
+#[derive(Debug, Error)]
+pub enum RulesLoaderError {
+    #[error("Could not load rules: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid rules format:\n{0}")]
+    Invalid(MapDiagnostics),
+}
+
+impl AssetLoader for RulesAssetLoader {
+    type Asset = Rules;
+    type Settings = ();
+    type Error = RulesLoaderError;
+
+    fn extensions(&self) -> &[&str] {
+        &["rules.toml"]
+    }
+
+    fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &Self::Settings,
+        _load_context: &mut LoadContext,
+    ) -> impl bevy::tasks::ConditionalSendFuture<Output = std::result::Result<Self::Asset, Self::Error>>
+    {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let content = std::str::from_utf8(&bytes).map_err(|err| {
+                let valid = std::str::from_utf8(&bytes[..err.valid_up_to()]).unwrap_or_default();
+                let mut diagnostics = MapDiagnostics::default();
+                diagnostics.error(
+                    Some(MapLocation::from_offset(valid, valid.len())),
+                    "Invalid UTF-8 content",
+                );
+                RulesLoaderError::Invalid(diagnostics)
+            })?;
+            parse_rules(content)
+        })
+    }
+}
+
+fn property(path: &str) -> Option<MapLocation> {
+    Some(MapLocation::Property(path.into()))
+}
+
+fn parse_number<T: TryFrom<i64>>(
+    source: &Table,
+    path: &str,
+    key: &str,
+    diagnostics: &mut MapDiagnostics,
+) -> Option<T> {
+    let path = format!("{path}.{key}");
+    let Some(value) = source.get(key) else {
+        diagnostics.error(property(&path), "Missing value");
+        return None;
+    };
+    let number = value
+        .as_integer()
+        .and_then(|number| T::try_from(number).ok());
+    if number.is_none() {
+        diagnostics.error(
+            property(&path),
+            "Invalid value, it should be a positive number",
+        );
+    }
+    number
+}
+
+fn parse_bool(
+    source: &Table,
+    path: &str,
+    key: &str,
+    diagnostics: &mut MapDiagnostics,
+) -> Option<bool> {
+    let path = format!("{path}.{key}");
+    let value = source.get(key).and_then(Value::as_bool);
+    if value.is_none() {
+        diagnostics.error(property(&path), "It should be true or false");
+    }
+    value
+}
+
+fn parse_classes(
+    source: &Table,
+    path: &str,
+    key: &str,
+    diagnostics: &mut MapDiagnostics,
+) -> Option<Vec<UnitClass>> {
+    let path = format!("{path}.{key}");
+    let Some(value) = source.get(key) else {
+        return Some(vec![]);
+    };
+    let Some(classes) = value.as_array() else {
+        diagnostics.error(property(&path), "It should be a list of unit classes");
+        return None;
+    };
+    let mut result = vec![];
+    let mut valid = true;
+    for class in classes {
+        match class
+            .as_str()
+            .and_then(|class| UnitClass::try_from(class).ok())
+        {
+            Some(class) => result.push(class),
+            None => {
+                diagnostics.error(property(&path), format!("Invalid unit class {class}"));
+                valid = false;
+            }
+        }
+    }
+    valid.then_some(result)
+}
+
+fn parse_range(
+    source: &Table,
+    path: &str,
+    diagnostics: &mut MapDiagnostics,
+) -> Option<Option<AttackRange>> {
+    let Some(range) = source.get("range") else {
+        return Some(None);
+    };
+    let values: Option<Vec<u32>> = range.as_array().and_then(|values| {
+        values
+            .iter()
+            .map(|value| {
+                value
+                    .as_integer()
+                    .and_then(|value| u32::try_from(value).ok())
+            })
+            .collect()
+    });
+    match values.as_deref() {
+        Some([min, max]) if min <= max => Some(Some(AttackRange {
+            min: *min,
+            max: *max,
+        })),
+        _ => {
+            diagnostics.error(
+                property(&format!("{path}.range")),
+                "Range should be a list with the minimum and maximum distance",
+            );
+            None
+        }
+    }
+}
+
+fn parse_unit(source: &Table, path: &str, diagnostics: &mut MapDiagnostics) -> Option<UnitStats> {
+    let mov_type = match source.get("movement_type").and_then(Value::as_str) {
+        None => {
+            diagnostics.error(
+                property(&format!("{path}.movement_type")),
+                "Movement type must be specified",
+            );
+            None
+        }
+        Some(mov_type) => {
+            let parsed = MovementType::try_from(mov_type).ok();
+            if parsed.is_none() {
+                diagnostics.error(
+                    property(&format!("{path}.movement_type")),
+                    format!("Invalid movement type: {mov_type}"),
+                );
+            }
+            parsed
+        }
+    };
+
+    let cost = parse_number(source, path, "cost", diagnostics);
+    let movement = parse_number(source, path, "movement", diagnostics);
+    let vision = parse_number(source, path, "vision", diagnostics);
+    let mountain_vision = parse_number(source, path, "mountain_vision", diagnostics);
+    let fuel = parse_number(source, path, "fuel", diagnostics);
+    let ammo = parse_number(source, path, "ammo", diagnostics);
+    let attack_range = parse_range(source, path, diagnostics);
+    Some(UnitStats {
+        cost: cost?,
+        movement: movement?,
+        mov_type: mov_type?,
+        vision: vision?,
+        mountain_vision: mountain_vision?,
+        fuel: fuel?,
+        ammo: ammo?,
+        attack_range: attack_range?,
+    })
+}
+
+fn parse_building(
+    source: &Table,
+    path: &str,
+    diagnostics: &mut MapDiagnostics,
+) -> Option<BuildingStats> {
+    let income = parse_number(source, path, "income", diagnostics).map(Income);
+    let capture_points = parse_number(source, path, "capture_points", diagnostics);
+    let defense = parse_number(source, path, "defense", diagnostics);
+    let vision = parse_number(source, path, "vision", diagnostics);
+    let builds = parse_classes(source, path, "builds", diagnostics);
+    let repairs = parse_classes(source, path, "repairs", diagnostics);
+    Some(BuildingStats {
+        income: income?,
+        capture_points: capture_points?,
+        defense: defense?,
+        vision: vision?,
+        builds: builds?,
+        repairs: repairs?,
+    })
+}
+
+fn parse_victory(
+    source: &Table,
+    path: &str,
+    diagnostics: &mut MapDiagnostics,
+) -> Option<VictoryRules> {
+    let headquarters = parse_bool(source, path, "headquarters", diagnostics);
+    let rout = parse_bool(source, path, "rout", diagnostics);
+    let capture_buildings = parse_number::<u32>(source, path, "capture_buildings", diagnostics);
+    Some(VictoryRules {
+        headquarters: headquarters?,
+        rout: rout?,
+        capture_buildings: capture_buildings.filter(|buildings| *buildings > 0),
+    })
+}
+
+fn parse_pathfinding(
+    source: &Table,
+    path: &str,
+    diagnostics: &mut MapDiagnostics,
+) -> Option<PathfindingRules> {
+    let pass_through_allies = parse_bool(source, path, "pass_through_allies", diagnostics);
+    let ambush = parse_bool(source, path, "ambush", diagnostics);
+    Some(PathfindingRules {
+        pass_through_allies: pass_through_allies?,
+        ambush: ambush?,
+    })
+}
+
+/// Reads the damage tables of the weapons of each unit, the units without weapons
+/// are not there. The damage against breakable walls is under the `wall` key.
+#[allow(clippy::type_complexity)]
+fn parse_damage(
+    source: &Table,
+    diagnostics: &mut MapDiagnostics,
+) -> (
+    HashMap<(UnitType, Weapon, UnitType), u32>,
+    HashMap<(UnitType, Weapon), u32>,
+) {
+    let mut damage = HashMap::new();
+    let mut wall_damage = HashMap::new();
+    for (attacker_name, weapons) in source {
+        let path = format!("damage.{attacker_name}");
+        let Ok(attacker) = UnitType::try_from(attacker_name.as_str()) else {
+            diagnostics.error(property(&path), format!("Unknown unit {attacker_name}"));
+            continue;
+        };
+        let Some(weapons) = weapons.as_table() else {
+            diagnostics.error(property(&path), "It should be a table of weapons");
+            continue;
+        };
+        for (weapon_name, defenders) in weapons {
+            let path = format!("{path}.{weapon_name}");
+            let Some(weapon) = Weapon::ALL
+                .into_iter()
+                .find(|weapon| weapon.name() == weapon_name)
+            else {
+                diagnostics.error(property(&path), format!("Unknown weapon {weapon_name}"));
+                continue;
+            };
+            let Some(defenders) = defenders.as_table() else {
+                diagnostics.error(property(&path), "It should be a table of units");
+                continue;
+            };
+            for defender_name in defenders.keys() {
+                if defender_name == WALL_KEY {
+                    if let Some(value) = parse_number(defenders, &path, WALL_KEY, diagnostics) {
+                        wall_damage.insert((attacker, weapon), value);
+                    }
+                    continue;
+                }
+                let Ok(defender) = UnitType::try_from(defender_name.as_str()) else {
+                    diagnostics.error(
+                        property(&format!("{path}.{defender_name}")),
+                        format!("Unknown unit {defender_name}"),
+                    );
+                    continue;
+                };
+                if let Some(value) = parse_number(defenders, &path, defender_name, diagnostics) {
+                    damage.insert((attacker, weapon, defender), value);
+                }
+            }
+        }
+    }
+    (damage, wall_damage)
+}
+
+/// Gets the table in the key, reporting it when it is not a table
+fn get_table<'a>(
+    source: &'a Table,
+    path: &str,
+    diagnostics: &mut MapDiagnostics,
+) -> Option<&'a Table> {
+    let key = path.rsplit('.').next().unwrap_or(path);
+    let table = source.get(key).and_then(Value::as_table);
+    if table.is_none() {
+        diagnostics.error(property(path), "Missing table");
+    }
+    table
+}
+
+/// Reads a table of values per terrain, warning about the unknown terrain names
+fn parse_terrain_table<T: TryFrom<i64>>(
+    source: &Table,
+    path: &str,
+    diagnostics: &mut MapDiagnostics,
+) -> HashMap<Terrain, T> {
+    let mut values = HashMap::new();
+    for key in source.keys() {
+        let Some(terrain) = Terrain::from_name(key) else {
+            diagnostics.error(
+                property(&format!("{path}.{key}")),
+                format!("Unknown terrain {key}"),
+            );
+            continue;
+        };
+        if let Some(value) = parse_number(source, path, key, diagnostics) {
+            values.insert(terrain, value);
+        }
+    }
+    values
+}
+
+/// Parses the rules, every unit and building has to be defined
+pub fn parse_rules(content: &str) -> Result<Rules, RulesLoaderError> {
+    let mut diagnostics = MapDiagnostics::default();
+    let source = match content.parse::<Table>() {
+        Ok(source) => source,
+        Err(err) => {
+            let location = err
+                .span()
+                .map(|span| MapLocation::from_offset(content, span.start));
+            diagnostics.error(location, err.message());
+            return Err(RulesLoaderError::Invalid(diagnostics));
+        }
+    };
+
+    let mut units = HashMap::new();
+    if let Some(units_source) = get_table(&source, "units", &mut diagnostics) {
+        for unit_type in UnitType::ALL {
+            let path = format!("units.{}", unit_type.name());
+            if let Some(stats) = get_table(units_source, &path, &mut diagnostics)
+                .and_then(|unit_source| parse_unit(unit_source, &path, &mut diagnostics))
+            {
+                units.insert(unit_type, stats);
+            }
+        }
+    }
+
+    let mut movement_costs = HashMap::new();
+    if let Some(movement_source) = get_table(&source, "movement", &mut diagnostics) {
+        for mov_type in MovementType::ALL {
+            let path = format!("movement.{}", mov_type.name());
+            let Some(costs_source) = get_table(movement_source, &path, &mut diagnostics) else {
+                continue;
+            };
+            for (terrain, cost) in parse_terrain_table(costs_source, &path, &mut diagnostics) {
+                movement_costs.insert((mov_type, terrain), cost);
+            }
+        }
+    }
+
+    let terrain_defense = match get_table(&source, "defense", &mut diagnostics) {
+        Some(defense_source) => parse_terrain_table(defense_source, "defense", &mut diagnostics),
+        None => HashMap::new(),
+    };
+
+    let mut buildings = HashMap::new();
+    if let Some(buildings_source) = get_table(&source, "buildings", &mut diagnostics) {
+        for building_type in BuildingType::ALL {
+            let path = format!("buildings.{}", building_type.name());
+            if let Some(stats) =
+                get_table(buildings_source, &path, &mut diagnostics).and_then(|building_source| {
+                    parse_building(building_source, &path, &mut diagnostics)
+                })
+            {
+                buildings.insert(building_type, stats);
+            }
+        }
+    }
+
+    let pathfinding =
+        get_table(&source, "pathfinding", &mut diagnostics).and_then(|pathfinding_source| {
+            parse_pathfinding(pathfinding_source, "pathfinding", &mut diagnostics)
+        });
+
+    let (damage, wall_damage) = match get_table(&source, "damage", &mut diagnostics) {
+        Some(damage_source) => parse_damage(damage_source, &mut diagnostics),
+        None => (HashMap::new(), HashMap::new()),
+    };
+
+    let victory = get_table(&source, "victory", &mut diagnostics)
+        .and_then(|victory_source| parse_victory(victory_source, "victory", &mut diagnostics));
+
+    let (Some(pathfinding), Some(victory)) = (pathfinding, victory) else {
+        return Err(RulesLoaderError::Invalid(diagnostics));
+    };
+    if diagnostics.has_errors() {
+        return Err(RulesLoaderError::Invalid(diagnostics));
+    }
+    Ok(Rules {
+        units,
+        movement_costs,
+        terrain_defense,
+        buildings,
+        pathfinding,
+        damage,
+        wall_damage,
+        victory,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interactive::Life;
+
+    #[test]
+    fn test_builtin_rules() {
+        let rules = Rules::builtin();
+        assert_eq!(rules.unit(UnitType::Infantry).movement, 30);
+        assert_eq!(
+            rules.movement_cost(MovementType::Foot, &Terrain::Mountain),
+            Some(20)
+        );
+        assert_eq!(
+            rules.movement_cost(MovementType::Ship, &Terrain::Plane),
+            None
+        );
+        assert_eq!(rules.defense(&Terrain::Forest), 2);
+        assert_eq!(
+            rules.building(BuildingType::Factory).builds,
+            vec![UnitClass::Land]
+        );
+        assert!(rules.pathfinding().pass_through_allies);
+        assert!(rules.pathfinding().ambush);
+        assert_eq!(
+            rules.victory(),
+            &VictoryRules {
+                headquarters: true,
+                rout: true,
+                capture_buildings: None,
+            }
+        );
+        assert_eq!(
+            rules.base_damage(UnitType::Tank, Weapon::Primary, UnitType::Tank),
+            Some(55)
+        );
+        assert_eq!(
+            rules.base_damage(UnitType::Tank, Weapon::Primary, UnitType::Infantry),
+            None
+        );
+        assert_eq!(
+            rules.base_damage(UnitType::Infantry, Weapon::Secondary, UnitType::Mech),
+            Some(45)
+        );
+    }
+
+    #[test]
+    fn test_movement_cost_table() {
+        const X: Option<u32> = None;
+        // Plain, road, mountain, sea, beach, forest, bridge, river, wall, breakable wall
+        let table = [
+            (
+                MovementType::Foot,
+                [
+                    Some(10),
+                    Some(10),
+                    Some(20),
+                    X,
+                    Some(10),
+                    Some(10),
+                    Some(10),
+                    Some(20),
+                    X,
+                    X,
+                ],
+            ),
+            (
+                MovementType::Boots,
+                [
+                    Some(10),
+                    Some(10),
+                    Some(10),
+                    X,
+                    Some(10),
+                    Some(10),
+                    Some(10),
+                    Some(10),
+                    X,
+                    X,
+                ],
+            ),
+            (
+                MovementType::Weels,
+                [
+                    Some(20),
+                    Some(10),
+                    X,
+                    X,
+                    Some(10),
+                    Some(30),
+                    Some(10),
+                    X,
+                    X,
+                    X,
+                ],
+            ),
+            (
+                MovementType::Treads,
+                [
+                    Some(10),
+                    Some(10),
+                    X,
+                    X,
+                    Some(10),
+                    Some(20),
+                    Some(10),
+                    X,
+                    X,
+                    X,
+                ],
+            ),
+            (
+                MovementType::Air,
+                [
+                    Some(10),
+                    Some(10),
+                    Some(10),
+                    Some(10),
+                    Some(10),
+                    Some(10),
+                    Some(10),
+                    Some(10),
+                    X,
+                    X,
+                ],
+            ),
+            (MovementType::Ship, [X, X, X, Some(10), X, X, X, X, X, X]),
+            (
+                MovementType::Lander,
+                [X, X, X, Some(10), Some(10), X, X, X, X, X],
+            ),
+        ];
+
+        let rules = Rules::builtin();
+        for (mov_type, costs) in table {
+            for (terrain, cost) in Terrain::KINDS.iter().zip(costs) {
+                assert_eq!(
+                    rules.movement_cost(mov_type, terrain),
+                    cost,
+                    "{mov_type:?} on {terrain:?}"
+                );
+            }
+        }
+        assert_eq!(
+            rules.movement_cost(MovementType::Foot, &Terrain::BreakableWall(Life(10))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_unit_movement_types() {
+        let rules = Rules::builtin();
+        assert_eq!(rules.unit(UnitType::Mech).mov_type, MovementType::Boots);
+        assert_eq!(rules.unit(UnitType::Reccon).mov_type, MovementType::Weels);
+        assert_eq!(rules.unit(UnitType::Tank).mov_type, MovementType::Treads);
+        assert_eq!(rules.unit(UnitType::Lander).mov_type, MovementType::Lander);
+        assert_eq!(rules.unit(UnitType::Lander).class(), UnitClass::Sea);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let content = include_str!("../../assets/default.rules.toml")
+            .replace("cost = 1000\n", "cost = -1\n")
+            .replace("[defense]\n", "[defense]\nlava = 3\n")
+            .replace(
+                "[damage.sub.primary]\n",
+                "[damage.sub.primary]\nboat = 10\n",
+            )
+            .replace("rout = true\n", "rout = 1\n");
+
+        let Err(RulesLoaderError::Invalid(diagnostics)) = parse_rules(&content) else {
+            panic!("The rules should be invalid");
+        };
+        let locations: Vec<_> = diagnostics
+            .0
+            .into_iter()
+            .filter_map(|diagnostic| diagnostic.location)
+            .collect();
+        assert_eq!(
+            locations,
+            vec![
+                MapLocation::Property("units.infantry.cost".into()),
+                MapLocation::Property("defense.lava".into()),
+                MapLocation::Property("damage.sub.primary.boat".into()),
+                MapLocation::Property("victory.rout".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_unit() {
+        let content =
+            include_str!("../../assets/default.rules.toml").replace("[units.sub]", "[sub]");
+
+        let Err(RulesLoaderError::Invalid(diagnostics)) = parse_rules(&content) else {
+            panic!("The rules should be invalid");
+        };
+        assert_eq!(
+            diagnostics.0[0].location,
+            Some(MapLocation::Property("units.sub".into()))
+        );
+    }
+}
@@ -3,7 +3,10 @@ use std::collections::HashSet;
 use auto_tiler::{AsMask, AutoTiler, Requirement, TileDefinition};
 use bevy::math::{UVec2, uvec2};
 
-use crate::board::{direction::Direction, map::Terrain, map::Terrain::*};
+use crate::{
+    board::{direction::Direction, map::Terrain, map::Terrain::*},
+    interactive::Life,
+};
 
 #[derive(Debug, Eq, Clone, Copy, PartialEq, Default, PartialOrd, Hash)]
 pub enum TileTerrain {
@@ -16,7 +19,9 @@ pub enum TileTerrain {
     Bridge,
     Forest,
     Wall,
-    // BreakableWall,
+    River,
+    BreakableWall,
+    DamagedWall,
 }
 
 impl From<&Terrain> for TileTerrain {
@@ -26,10 +31,13 @@ impl From<&Terrain> for TileTerrain {
             Sea => Self::Sea,
             Road => Self::Road,
             Mountain => Self::Mountain,
-            //"b" => Self::Bridge,
+            Bridge => Self::Bridge,
             Beach => Self::Beach,
-            //"w" => Self::Wall,
+            Wall => Self::Wall,
             Forest => Self::Forest,
+            River => Self::River,
+            BreakableWall(life) if *life == Life::new() => Self::BreakableWall,
+            BreakableWall(_) => Self::DamagedWall,
         }
     }
 }
@@ -255,6 +263,88 @@ fn add_mountain(auto_tiler: &mut AutoTiler<TileTerrain, UVec2>) {
     );
 }
 
+/// Bridges follow the road crossing them, or go across the water below them
+fn add_bridge(auto_tiler: &mut AutoTiler<TileTerrain, UVec2>) {
+    let terrain = TileTerrain::Bridge;
+    let roads = HashSet::from([TileTerrain::Road, TileTerrain::Bridge]);
+    let water = HashSet::from([TileTerrain::Sea, TileTerrain::River]);
+    let horizontal = uvec2(14, 3);
+    let vertical = uvec2(13, 2);
+
+    auto_tiler.add_tile(
+        TileDefinition::new(horizontal, terrain)
+            .add_possible_requirements(vec![Requirement::new(roads.clone(), &[Direction::East])])
+            .add_possible_requirements(vec![Requirement::new(roads.clone(), &[Direction::West])]),
+    );
+    auto_tiler.add_tile(
+        TileDefinition::new(vertical, terrain)
+            .add_possible_requirements(vec![Requirement::new(roads.clone(), &[Direction::North])])
+            .add_possible_requirements(vec![Requirement::new(roads, &[Direction::South])])
+            .change_priority(1),
+    );
+    auto_tiler.add_tile(
+        TileDefinition::new(horizontal, terrain)
+            .add_possible_requirements(vec![Requirement::new(water.clone(), &[Direction::North])])
+            .add_possible_requirements(vec![Requirement::new(water, &[Direction::South])])
+            .change_priority(2),
+    );
+    auto_tiler.add_tile(TileDefinition::new(vertical, terrain).change_priority(3));
+}
+
+/// Rivers connect like roads, with a single tile width
+fn add_river(auto_tiler: &mut AutoTiler<TileTerrain, UVec2>) {
+    let terrain = TileTerrain::River;
+    let neighbors = HashSet::from([TileTerrain::River, TileTerrain::Bridge, TileTerrain::Sea]);
+
+    calculate(
+        auto_tiler,
+        terrain,
+        &neighbors,
+        &UVec2::ZERO,
+        &[uvec2(1, 28), uvec2(2, 28), uvec2(22, 28), uvec2(21, 28)],
+        &[Direction::South, Direction::East],
+        &NotWanted::Rotated(&[Direction::North, Direction::West]),
+    );
+
+    auto_tiler.add_tile(
+        TileDefinition::new(uvec2(20, 28), terrain)
+            .add_possible_requirements(vec![Requirement::new(
+                neighbors.clone(),
+                &[Direction::East],
+            )])
+            .add_possible_requirements(vec![Requirement::new(neighbors, &[Direction::West])])
+            .change_priority(1),
+    );
+    auto_tiler.add_tile(TileDefinition::new(uvec2(0, 28), terrain).change_priority(2));
+}
+
+/// Walls are horizontal unless they continue to the north or the south
+fn add_walls(auto_tiler: &mut AutoTiler<TileTerrain, UVec2>) {
+    let walls = HashSet::from([
+        TileTerrain::Wall,
+        TileTerrain::BreakableWall,
+        TileTerrain::DamagedWall,
+    ]);
+    for (terrain, horizontal) in [
+        (TileTerrain::Wall, uvec2(4, 17)),
+        (TileTerrain::BreakableWall, uvec2(4, 18)),
+        (TileTerrain::DamagedWall, uvec2(4, 19)),
+    ] {
+        auto_tiler.add_tile(
+            TileDefinition::new(horizontal + uvec2(1, 0), terrain)
+                .add_possible_requirements(vec![Requirement::new(
+                    walls.clone(),
+                    &[Direction::North],
+                )])
+                .add_possible_requirements(vec![Requirement::new(
+                    walls.clone(),
+                    &[Direction::South],
+                )]),
+        );
+        auto_tiler.add_tile(TileDefinition::new(horizontal, terrain).change_priority(1));
+    }
+}
+
 pub fn build_auto_tiler() -> AutoTiler<TileTerrain, UVec2> {
     let mut auto_tiler = AutoTiler::default();
     auto_tiler.add_tile(
@@ -276,6 +366,9 @@ pub fn build_auto_tiler() -> AutoTiler<TileTerrain, UVec2> {
     );
 
     add_mountain(&mut auto_tiler);
+    add_bridge(&mut auto_tiler);
+    add_river(&mut auto_tiler);
+    add_walls(&mut auto_tiler);
 
     auto_tiler.add_tile(
         TileDefinition::new(uvec2(1, 40), TileTerrain::Forest)
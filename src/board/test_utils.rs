@@ -0,0 +1,42 @@
+use crate::board::{board::Board, map::parse_map, rules::Rules};
+
+/// Board of the map source with the built-in rules
+pub fn make_board(data: &str) -> Board {
+    Board::new(parse_map(data).unwrap())
+}
+
+/// Board of the map source with the given rules
+pub fn make_board_with_rules(data: &str, rules: Rules) -> Board {
+    Board::with_rules(parse_map(data).unwrap(), rules)
+}
+
+/// Source of a map with a single row of terrain codes, the toml lines of its
+/// buildings and units and any other tables, like `[players]` or `[settings]`
+pub fn row_map(terrain: &[&str], buildings: &str, units: &str, tables: &str) -> String {
+    grid_map(&[terrain], buildings, units, tables)
+}
+
+/// Same as [`row_map`] with several rows of terrain, the first one at the bottom
+pub fn grid_map(rows: &[&[&str]], buildings: &str, units: &str, tables: &str) -> String {
+    let terrain: Vec<String> = rows
+        .iter()
+        .map(|row| {
+            let cells: Vec<String> = row.iter().map(|code| format!("\"{code}\"")).collect();
+            format!("[{}]", cells.join(", "))
+        })
+        .collect();
+    format!(
+        "version=2
+        width={}
+        height={}
+        terrain = [{}]
+        {tables}
+        [buildings]
+        {buildings}
+        [units]
+        {units}",
+        rows[0].len(),
+        rows.len(),
+        terrain.join(", ")
+    )
+}
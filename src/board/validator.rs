@@ -0,0 +1,343 @@
+use std::collections::{BTreeSet, HashSet, VecDeque};
+
+use crate::{
+    board::{
+        diagnostics::{MapDiagnostics, MapLocation},
+        map::{BuildingType, Map},
+        rules::Rules,
+    },
+    interactive::{MovementType, PlayerId},
+};
+
+/// Checks that a parsed map is playable: units and buildings are on valid terrain,
+/// every player has a headquarters and it can be reached by the enemy infantry.
+pub fn validate_map(map: &Map, rules: &Rules) -> MapDiagnostics {
+    let mut diagnostics = MapDiagnostics::default();
+
+    let mut players: BTreeSet<PlayerId> = map.players.iter().map(|player| player.id).collect();
+    let slots = players.clone();
+    let mut owners_with_buildings = HashSet::new();
+    let mut owners_with_units = HashSet::new();
+
+    for pos in map.cells.keys() {
+        let cell = &map.cells[pos];
+        if let Some(building) = &cell.building {
+            if !building.build_type.allowed_on(&cell.terrain) {
+                diagnostics.error(
+                    Some(MapLocation::cell(pos)),
+                    format!(
+                        "{:?} can not be placed on {:?}",
+                        building.build_type, cell.terrain
+                    ),
+                );
+            }
+            if building.owner.0 != 0 {
+                players.insert(building.owner.0);
+                owners_with_buildings.insert(building.owner.0);
+            }
+        }
+        if let Some(unit) = &cell.unit {
+            let mov_type = rules.unit(unit.unit_type).mov_type;
+            if rules.movement_cost(mov_type, &cell.terrain).is_none() {
+                diagnostics.error(
+                    Some(MapLocation::cell(pos)),
+                    format!(
+                        "{:?} with {:?} movement can not stand on {:?}",
+                        unit.unit_type, mov_type, cell.terrain
+                    ),
+                );
+            }
+            players.insert(unit.owner.0);
+            owners_with_units.insert(unit.owner.0);
+        }
+    }
+
+    if !map.players.is_empty() {
+        for player in players.difference(&slots) {
+            diagnostics.error(
+                Some(MapLocation::Player(player.to_string())),
+                "Player owns units or buildings but has no slot in the players table",
+            );
+        }
+    }
+    if players.len() < 2 {
+        diagnostics.warning(None, "The map needs at least two players to be played");
+    }
+
+    for player in players.iter() {
+        let headquarters = map
+            .cells
+            .keys()
+            .into_iter()
+            .filter(|pos| {
+                map.cells[*pos].building.is_some_and(|building| {
+                    building.owner.0 == *player && building.build_type == BuildingType::Headquarters
+                })
+            })
+            .collect::<Vec<_>>();
+
+        match headquarters.len() {
+            0 => diagnostics.error(
+                Some(MapLocation::Player(player.to_string())),
+                "Player has no headquarters",
+            ),
+            1 => {}
+            _ => diagnostics.error(
+                Some(MapLocation::Player(player.to_string())),
+                format!("Player has {} headquarters", headquarters.len()),
+            ),
+        }
+
+        if owners_with_units.contains(player) && !owners_with_buildings.contains(player) {
+            diagnostics.warning(
+                Some(MapLocation::Player(player.to_string())),
+                "Player has units but no buildings",
+            );
+        }
+
+        let reachable = reachable_by_enemies(map, rules, *player);
+        if reachable.is_empty() {
+            continue;
+        }
+        for pos in headquarters {
+            if !reachable.contains(&pos) {
+                diagnostics.warning(
+                    Some(MapLocation::cell(pos)),
+                    format!("Headquarters of player {player} can not be reached by enemy infantry"),
+                );
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// Cells that can be reached walking from any unit or building of the other players
+fn reachable_by_enemies(map: &Map, rules: &Rules, player: PlayerId) -> HashSet<(usize, usize)> {
+    let walkable = |pos: (usize, usize)| {
+        rules
+            .movement_cost(MovementType::Foot, &map.cells[pos].terrain)
+            .is_some()
+    };
+    let mut pending: VecDeque<_> = map
+        .cells
+        .keys()
+        .into_iter()
+        .filter(|pos| {
+            let cell = &map.cells[*pos];
+            let enemy_building = cell
+                .building
+                .is_some_and(|building| building.owner.0 != 0 && building.owner.0 != player);
+            let enemy_unit = cell.unit.is_some_and(|unit| unit.owner.0 != player);
+            (enemy_building || enemy_unit) && walkable(*pos)
+        })
+        .collect();
+    let mut reachable: HashSet<_> = pending.iter().copied().collect();
+
+    let (width, height) = map.get_size();
+    while let Some((x, y)) = pending.pop_front() {
+        let neighbors = [
+            x.checked_sub(1).map(|x| (x, y)),
+            y.checked_sub(1).map(|y| (x, y)),
+            (x + 1 < width).then_some((x + 1, y)),
+            (y + 1 < height).then_some((x, y + 1)),
+        ];
+        for neighbor in neighbors.into_iter().flatten() {
+            if walkable(neighbor) && reachable.insert(neighbor) {
+                pending.push_back(neighbor);
+            }
+        }
+    }
+
+    reachable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{
+        diagnostics::{MapDiagnostic, Severity},
+        map::parse_map,
+    };
+
+    fn validate(data: &str) -> Vec<MapDiagnostic> {
+        validate_map(&parse_map(data).unwrap(), Rules::builtin()).0
+    }
+
+    #[test]
+    fn test_valid_map() {
+        let data = "version=1
+            width=3
+            height=1
+            terrain = [[\"p\", \"r\", \"p\"]]
+            [buildings]
+            1x1 = {type=\"headquarters\", owner=1}
+            3x1 = {type=\"headquarters\", owner=2}
+            [units]
+            1x1 = {type=\"infantry\", owner=1}
+            ";
+
+        assert_eq!(validate(data), vec![]);
+    }
+
+    #[test]
+    fn test_invalid_terrain() {
+        let data = "version=1
+            width=3
+            height=1
+            terrain = [[\"s\", \"s\", \"p\"]]
+            [buildings]
+            1x1 = {type=\"headquarters\", owner=1}
+            3x1 = {type=\"headquarters\", owner=2}
+            [units]
+            2x1 = {type=\"infantry\", owner=1}
+            ";
+
+        let diagnostics = validate(data);
+        assert!(diagnostics.contains(&MapDiagnostic {
+            severity: Severity::Error,
+            location: Some(MapLocation::Cell { x: 1, y: 1 }),
+            message: "Headquarters can not be placed on Sea".into(),
+        }));
+        assert!(diagnostics.contains(&MapDiagnostic {
+            severity: Severity::Error,
+            location: Some(MapLocation::Cell { x: 2, y: 1 }),
+            message: "Infantry with Foot movement can not stand on Sea".into(),
+        }));
+    }
+
+    #[test]
+    fn test_players_without_headquarters() {
+        let data = "version=1
+            width=3
+            height=1
+            terrain = [[\"p\", \"p\", \"p\"]]
+            [buildings]
+            1x1 = {type=\"headquarters\", owner=1}
+            2x1 = {type=\"headquarters\", owner=1}
+            [units]
+            3x1 = {type=\"infantry\", owner=2}
+            ";
+
+        assert_eq!(
+            validate(data),
+            vec![
+                MapDiagnostic {
+                    severity: Severity::Error,
+                    location: Some(MapLocation::Player("1".into())),
+                    message: "Player has 2 headquarters".into(),
+                },
+                MapDiagnostic {
+                    severity: Severity::Error,
+                    location: Some(MapLocation::Player("2".into())),
+                    message: "Player has no headquarters".into(),
+                },
+                MapDiagnostic {
+                    severity: Severity::Warning,
+                    location: Some(MapLocation::Player("2".into())),
+                    message: "Player has units but no buildings".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_headquarters() {
+        let data = "version=2
+            width=3
+            height=1
+            terrain = [[\"p\", \"s\", \"p\"]]
+            [players]
+            1 = {}
+            2 = {}
+            [buildings]
+            1x1 = {type=\"headquarters\", owner=1}
+            3x1 = {type=\"headquarters\", owner=2}
+            ";
+
+        let diagnostics = validate(data);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].location,
+            Some(MapLocation::Cell { x: 1, y: 1 })
+        );
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_owner_without_slot() {
+        let data = "version=2
+            width=2
+            height=1
+            terrain = [[\"p\", \"p\"]]
+            [players]
+            1 = {}
+            [buildings]
+            1x1 = {type=\"headquarters\", owner=1}
+            2x1 = {type=\"headquarters\", owner=2}
+            ";
+
+        let diagnostics = validate(data);
+        assert_eq!(
+            diagnostics[0],
+            MapDiagnostic {
+                severity: Severity::Error,
+                location: Some(MapLocation::Player("2".into())),
+                message: "Player owns units or buildings but has no slot in the players table"
+                    .into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_oil_rig_on_sea() {
+        let data = "version=1
+            width=4
+            height=1
+            terrain = [[\"p\", \"s\", \"p\", \"p\"]]
+            [buildings]
+            1x1 = {type=\"headquarters\", owner=1}
+            2x1 = {type=\"oilrig\"}
+            3x1 = {type=\"oilrig\"}
+            4x1 = {type=\"headquarters\", owner=2}
+            ";
+
+        let errors: Vec<_> = validate(data)
+            .into_iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .collect();
+        assert_eq!(
+            errors,
+            vec![MapDiagnostic {
+                severity: Severity::Error,
+                location: Some(MapLocation::Cell { x: 3, y: 1 }),
+                message: "OilRig can not be placed on Plane".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_player_slot_without_units() {
+        let data = "version=2
+            width=3
+            height=1
+            terrain = [[\"p\", \"p\", \"p\"]]
+            [players]
+            1 = {}
+            2 = {}
+            [buildings]
+            1x1 = {type=\"headquarters\", owner=1}
+            ";
+
+        let diagnostics = validate(data);
+        assert_eq!(
+            diagnostics,
+            vec![MapDiagnostic {
+                severity: Severity::Error,
+                location: Some(MapLocation::Player("2".into())),
+                message: "Player has no headquarters".into(),
+            }],
+            "A player without units is not warned about missing buildings"
+        );
+    }
+}
@@ -0,0 +1,333 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+
+use crate::{
+    board::{BuildingType, board::Board},
+    interactive::{Owner, PlayerId},
+};
+
+/// What each player did during the match, shown in the results
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct PlayerStats {
+    pub units_built: u32,
+    pub units_lost: u32,
+    pub units_destroyed: u32,
+    pub buildings_captured: u32,
+    pub income: u32,
+}
+
+/// Why a player is out of the match
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DefeatReason {
+    HeadquartersLost,
+    Routed,
+    Resigned,
+}
+
+/// Why the match ended
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum VictoryReason {
+    /// Every other team was defeated
+    LastStanding,
+    /// The winners own the buildings the rules ask for
+    Buildings,
+    /// The day limit passed, the team with the most buildings wins
+    DayLimit,
+}
+
+/// End of the match, there is a draw when there are no winners
+#[derive(Resource, Debug, PartialEq, Eq, Clone)]
+pub struct MatchResult {
+    pub winners: Vec<PlayerId>,
+    pub reason: VictoryReason,
+}
+
+impl Board {
+    /// Team of the player in the map, players without a slot are their own team
+    pub fn team(&self, player: PlayerId) -> u8 {
+        self.map()
+            .players
+            .iter()
+            .find(|slot| slot.id == player)
+            .map(|slot| slot.team)
+            .unwrap_or(player)
+    }
+
+    /// Players of the map still in the match
+    pub fn active_players(&self) -> Vec<PlayerId> {
+        self.map()
+            .players
+            .iter()
+            .map(|slot| slot.id)
+            .filter(|player| self.defeat_reason(*player).is_none())
+            .collect()
+    }
+
+    /// Players that lost their headquarters or, from the second day, all their
+    /// units, as the rules enable. They still have to be defeated.
+    pub fn pending_defeats(&self, day: u32) -> Vec<(PlayerId, DefeatReason)> {
+        let rules = self.rules().victory();
+        self.active_players()
+            .into_iter()
+            .filter_map(|player| {
+                if rules.headquarters && self.lost_headquarters(player) {
+                    Some((player, DefeatReason::HeadquartersLost))
+                } else if rules.rout && day > 1 && !self.has_units(player) {
+                    Some((player, DefeatReason::Routed))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// The match ends when a single team is left, a team owns the buildings the
+    /// rules ask for or the day limit of the map passed
+    pub fn check_victory(&self, day: u32) -> Option<MatchResult> {
+        let active = self.active_players();
+        let teams: HashSet<u8> = active.iter().map(|player| self.team(*player)).collect();
+        let starting_teams: HashSet<u8> = self
+            .map()
+            .players
+            .iter()
+            .map(|slot| self.team(slot.id))
+            .collect();
+        if teams.len() <= 1 && (starting_teams.len() > 1 || active.is_empty()) {
+            return Some(MatchResult {
+                winners: active,
+                reason: VictoryReason::LastStanding,
+            });
+        }
+
+        let mut owned: HashMap<u8, u32> = HashMap::new();
+        for building in self.buildings.values() {
+            if active.contains(&building.owner.0) {
+                *owned.entry(self.team(building.owner.0)).or_default() += 1;
+            }
+        }
+        let mut ranking: Vec<(u8, u32)> = owned.into_iter().collect();
+        ranking.sort_by_key(|(team, count)| (std::cmp::Reverse(*count), *team));
+        let leader = match ranking.as_slice() {
+            [] => None,
+            [(team, count)] => Some((*team, *count)),
+            [(team, count), (_, second), ..] => (count > second).then_some((*team, *count)),
+        };
+        let team_players = |team: u8| {
+            active
+                .iter()
+                .copied()
+                .filter(|player| self.team(*player) == team)
+                .collect()
+        };
+
+        if let Some(goal) = self.rules().victory().capture_buildings
+            && let Some((team, count)) = leader
+            && count >= goal
+        {
+            return Some(MatchResult {
+                winners: team_players(team),
+                reason: VictoryReason::Buildings,
+            });
+        }
+        if self
+            .map()
+            .settings
+            .day_limit
+            .is_some_and(|limit| day > limit)
+        {
+            return Some(MatchResult {
+                winners: leader
+                    .map(|(team, _)| team_players(team))
+                    .unwrap_or_default(),
+                reason: VictoryReason::DayLimit,
+            });
+        }
+        None
+    }
+
+    /// The player started with a headquarters and doesn't own one anymore
+    fn lost_headquarters(&self, player: PlayerId) -> bool {
+        let is_headquarters = |owner: Owner, build_type: BuildingType| {
+            owner.0 == player && build_type == BuildingType::Headquarters
+        };
+        self.map()
+            .cells
+            .iter()
+            .filter_map(|cell| cell.building)
+            .any(|building| is_headquarters(building.owner, building.build_type))
+            && !self
+                .buildings
+                .values()
+                .any(|building| is_headquarters(building.owner, building.build_type))
+    }
+
+    fn has_units(&self, player: PlayerId) -> bool {
+        self.units.values().any(|unit| unit.owner.0 == player)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{
+        rules::{Rules, parse_rules},
+        test_utils::{make_board_with_rules, row_map},
+    };
+
+    const PLAINS: &[&str] = &["p"; 4];
+
+    /// Default rules with the number of buildings to capture to win, 0 disables it
+    fn rules(capture_buildings: u32) -> Rules {
+        let rules = include_str!("../../assets/default.rules.toml").replace(
+            "capture_buildings = 0\n",
+            &format!("capture_buildings = {capture_buildings}\n"),
+        );
+        parse_rules(&rules).unwrap()
+    }
+
+    #[test]
+    fn test_headquarters_lost() {
+        let mut board = make_board_with_rules(
+            &row_map(
+                PLAINS,
+                "1x1 = {type=\"headquarters\", owner=1}
+                4x1 = {type=\"headquarters\", owner=2}",
+                "2x1 = {type=\"infantry\", owner=1}
+                3x1 = {type=\"infantry\", owner=2}",
+                "[players]\n1 = {}\n2 = {}",
+            ),
+            rules(0),
+        );
+        assert_eq!(board.pending_defeats(1), vec![]);
+        assert_eq!(board.check_victory(1), None);
+
+        board.buildings.get_mut(&uvec2(3, 0)).unwrap().owner = Owner(1);
+        assert_eq!(
+            board.pending_defeats(1),
+            vec![(2, DefeatReason::HeadquartersLost)]
+        );
+        board.defeat(2, DefeatReason::HeadquartersLost);
+        assert!(!board.units.contains_key(&uvec2(2, 0)));
+        assert_eq!(board.pending_defeats(1), vec![], "Players lose only once");
+        assert_eq!(
+            board.check_victory(1),
+            Some(MatchResult {
+                winners: vec![1],
+                reason: VictoryReason::LastStanding
+            })
+        );
+    }
+
+    #[test]
+    fn test_rout() {
+        let mut board = make_board_with_rules(
+            &row_map(
+                PLAINS,
+                "",
+                "1x1 = {type=\"infantry\", owner=1}
+                4x1 = {type=\"infantry\", owner=3}",
+                "[players]\n1 = {}\n2 = {}\n3 = {team=1}",
+            ),
+            rules(0),
+        );
+        assert_eq!(
+            board.pending_defeats(1),
+            vec![],
+            "Nobody is routed on day one"
+        );
+
+        assert_eq!(board.pending_defeats(2), vec![(2, DefeatReason::Routed)]);
+        board.defeat(2, DefeatReason::Routed);
+        assert_eq!(
+            board.check_victory(2),
+            Some(MatchResult {
+                winners: vec![1, 3],
+                reason: VictoryReason::LastStanding
+            }),
+            "Players of the same team win together"
+        );
+    }
+
+    #[test]
+    fn test_resign() {
+        let mut board = make_board_with_rules(
+            &row_map(
+                PLAINS,
+                "1x1 = {type=\"city\", owner=2}",
+                "2x1 = {type=\"infantry\", owner=1}
+                3x1 = {type=\"infantry\", owner=2}",
+                "[players]\n1 = {}\n2 = {}",
+            ),
+            rules(0),
+        );
+        assert_eq!(board.defeat(2, DefeatReason::Resigned), vec![uvec2(0, 0)]);
+        assert_eq!(board.defeat_reason(2), Some(DefeatReason::Resigned));
+        assert_eq!(board.active_players(), vec![1]);
+        assert_eq!(board.buildings[&uvec2(0, 0)].owner, Owner(0));
+        assert_eq!(board.units.len(), 1);
+        assert_eq!(
+            board.stats(2).units_lost,
+            0,
+            "Units removed on defeat are not lost in battle"
+        );
+    }
+
+    #[test]
+    fn test_capture_buildings() {
+        let mut board = make_board_with_rules(
+            &row_map(
+                PLAINS,
+                "1x1 = {type=\"city\", owner=1}
+                2x1 = {type=\"city\", owner=1}
+                3x1 = {type=\"city\", owner=2}",
+                "4x1 = {type=\"infantry\", owner=2}",
+                "[players]\n1 = {}\n2 = {}",
+            ),
+            rules(3),
+        );
+        assert_eq!(board.check_victory(1), None);
+
+        board.buildings.get_mut(&uvec2(2, 0)).unwrap().owner = Owner(1);
+        assert_eq!(
+            board.check_victory(1),
+            Some(MatchResult {
+                winners: vec![1],
+                reason: VictoryReason::Buildings
+            })
+        );
+    }
+
+    #[test]
+    fn test_day_limit() {
+        let mut board = make_board_with_rules(
+            &row_map(
+                PLAINS,
+                "1x1 = {type=\"city\", owner=1}
+                2x1 = {type=\"city\", owner=2}",
+                "3x1 = {type=\"infantry\", owner=1}
+                4x1 = {type=\"infantry\", owner=2}",
+                "[players]\n1 = {}\n2 = {}\n[settings]\nday_limit = 3",
+            ),
+            rules(0),
+        );
+        assert_eq!(board.check_victory(3), None);
+        assert_eq!(
+            board.check_victory(4),
+            Some(MatchResult {
+                winners: vec![],
+                reason: VictoryReason::DayLimit
+            }),
+            "A tie is a draw"
+        );
+
+        board.buildings.get_mut(&uvec2(1, 0)).unwrap().owner = Owner(1);
+        assert_eq!(
+            board.check_victory(4),
+            Some(MatchResult {
+                winners: vec![1],
+                reason: VictoryReason::DayLimit
+            })
+        );
+    }
+}
@@ -1,7 +1,5 @@
 use bevy::prelude::*;
 
-use crate::board::Terrain;
-
 pub type PlayerId = u8;
 pub type CapturePoints = u8;
 
@@ -21,18 +19,20 @@ impl From<(usize, usize)> for BoardPos {
 }
 
 impl From<UVec2> for BoardPos {
-    fn from (value: UVec2) -> Self {
+    fn from(value: UVec2) -> Self {
         Self(value)
     }
 }
 
-#[derive(Component, Debug, PartialEq, Clone, Copy)]
+#[derive(Component, Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Owner(pub PlayerId);
 
-#[derive(Component, Debug, PartialEq, Clone, Copy)]
+#[derive(Component, Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct Life(pub u8);
 
 impl Life {
+    /// Full life, there is no obvious default between a full and an empty unit
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         Self(100)
     }
@@ -52,6 +52,13 @@ impl Capturable {
         }
     }
 
+    /// Points left to finish the capture in progress
+    pub fn pending(&self) -> Option<CapturePoints> {
+        self.current.as_ref().map(|capture| capture.pending)
+    }
+
+    /// Takes points from the building, a capture by another player starts over.
+    /// Returns true when the capture is completed.
     pub fn capture(&mut self, player: PlayerId, points: CapturePoints) -> bool {
         let capture = match &mut self.current {
             None => {
@@ -66,7 +73,7 @@ impl Capturable {
             }
         };
 
-        if capture.pending < points {
+        if capture.pending <= points {
             self.current = None;
             true
         } else {
@@ -89,36 +96,59 @@ impl Capture {
     }
 }
 
-#[derive(Component, Debug, Clone, Copy)]
+#[derive(Component, Debug, PartialEq, Clone, Copy)]
 // Will be nice to be able to force to have owner
 pub struct Income(pub u32);
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum MovementType {
+    /// Infantry
     Foot,
+    /// Mechs, they climb mountains and cross rivers easily
+    Boots,
+    /// Wheeled vehicles, fast on roads but slow off them
     Weels,
+    /// Tracked vehicles
+    Treads,
+    Air,
+    Ship,
+    /// Transport ships, they can land on beaches
+    Lander,
 }
 
-impl MovementType {
-    fn foot_costs(terrain: &Terrain) -> Option<u32> {
-        match terrain {
-            Terrain::Plane => Some(10),
-            Terrain::Forest => Some(15),
-            Terrain::Road => Some(10),
-            Terrain::Mountain => Some(20),
-            _ => None
-        }
+pub struct UnknownMovementType;
+impl TryFrom<&str> for MovementType {
+    type Error = UnknownMovementType;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|mov_type| mov_type.name() == value.to_lowercase())
+            .ok_or(UnknownMovementType)
     }
-    pub fn cost(&self, terrain: &Terrain) -> Option<u32> {
+}
+
+impl MovementType {
+    pub const ALL: [MovementType; 7] = [
+        Self::Foot,
+        Self::Boots,
+        Self::Weels,
+        Self::Treads,
+        Self::Air,
+        Self::Ship,
+        Self::Lander,
+    ];
+
+    /// Name used in the rules files
+    pub fn name(&self) -> &'static str {
         match self {
-            Self::Foot => MovementType::foot_costs(terrain),
-            _ => None,
+            Self::Foot => "foot",
+            Self::Boots => "boots",
+            Self::Weels => "weels",
+            Self::Treads => "treads",
+            Self::Air => "air",
+            Self::Ship => "ship",
+            Self::Lander => "lander",
         }
     }
 }
-
-#[derive(Component, Copy, Clone, Debug, PartialEq)]
-pub struct Movement {
-    pub mov_type: MovementType,
-    pub movements: u32,
-}
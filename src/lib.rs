@@ -0,0 +1,32 @@
+use bevy::prelude::*;
+
+pub mod animations;
+pub mod assets;
+pub mod board;
+pub mod interactive;
+pub mod matrix;
+pub mod menus;
+pub mod turns;
+pub mod ui;
+
+use crate::board::ShowBoard;
+
+#[derive(States, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub enum GameState {
+    #[default]
+    Menus,
+    InGame,
+    InEditor,
+}
+
+impl ComputedStates for ShowBoard {
+    type SourceStates = Option<GameState>;
+
+    fn compute(sources: Self::SourceStates) -> Option<Self> {
+        match sources {
+            Some(GameState::InGame) => Some(ShowBoard),
+            Some(GameState::InEditor) => Some(ShowBoard),
+            _ => None,
+        }
+    }
+}
@@ -1,27 +1,15 @@
 use bevy::prelude::*;
 use bevy_flair::FlairPlugin;
 
-mod animations;
-mod assets;
-mod board;
-mod interactive;
-mod matrix;
-mod menus;
-mod ui;
-
-use crate::{
+use advance_bevy_wars::{
+    GameState,
+    animations::AnimationPlugin,
     board::{BoardPlugin, ShowBoard},
     menus::MenusPlugin,
+    turns::TurnPlugin,
+    ui::UiPlugin,
 };
 
-#[derive(States, Clone, PartialEq, Eq, Hash, Debug, Default)]
-pub enum GameState {
-    #[default]
-    Menus,
-    InGame,
-    InEditor,
-}
-
 fn main() {
     let mut app = App::new();
     app.add_plugins((
@@ -38,9 +26,10 @@ fn main() {
     .add_computed_state::<ShowBoard>()
     .add_plugins((
         BoardPlugin,
-        crate::ui::UiPlugin,
-        crate::animations::AnimationPlugin,
+        UiPlugin,
+        AnimationPlugin,
         MenusPlugin,
+        TurnPlugin,
     ))
     .add_systems(Startup, setup);
 
@@ -50,15 +39,3 @@ fn main() {
 fn setup(mut commands: Commands) {
     commands.spawn((Camera2d, Transform::default()));
 }
-
-impl ComputedStates for ShowBoard {
-    type SourceStates = Option<GameState>;
-
-    fn compute(sources: Self::SourceStates) -> Option<Self> {
-        match sources {
-            Some(GameState::InGame) => Some(ShowBoard),
-            Some(GameState::InEditor) => Some(ShowBoard),
-            _ => None,
-        }
-    }
-}
@@ -0,0 +1,304 @@
+use bevy::prelude::*;
+
+use crate::{
+    GameState,
+    board::{Board, BoardLoad, BuildingCaptured, DefeatReason, MatchResult, UnitComponent},
+    interactive::{BoardPos, PlayerId},
+};
+
+pub struct TurnPlugin;
+
+/// Steps of the turn of every player
+#[derive(SubStates, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+#[source(GameState = GameState::InGame)]
+pub enum TurnPhase {
+    /// The units are refreshed and the systems hooked to `TurnStarted` run
+    #[default]
+    Upkeep,
+    /// The player moves the units
+    Command,
+    /// The turn is passed to the next player
+    End,
+}
+
+/// Player whose turn it is and the day of the match
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct TurnState {
+    players: Vec<PlayerId>,
+    current: usize,
+    day: u32,
+}
+
+impl TurnState {
+    /// The first player starts on the first day, at least one player is needed
+    pub fn new(players: Vec<PlayerId>) -> Self {
+        let players = if players.is_empty() { vec![1] } else { players };
+        Self {
+            players,
+            current: 0,
+            day: 1,
+        }
+    }
+
+    pub fn player(&self) -> PlayerId {
+        self.players[self.current]
+    }
+
+    pub fn day(&self) -> u32 {
+        self.day
+    }
+
+    pub fn players(&self) -> &[PlayerId] {
+        &self.players
+    }
+
+    /// Takes the player out of the turn order, the next player plays when it was
+    /// their turn. Returns true when the turn passed. The last player is kept.
+    pub fn remove_player(&mut self, player: PlayerId) -> bool {
+        let Some(index) = self.players.iter().position(|id| *id == player) else {
+            return false;
+        };
+        if self.players.len() == 1 {
+            return false;
+        }
+        self.players.remove(index);
+        if index != self.current {
+            if index < self.current {
+                self.current -= 1;
+            }
+            return false;
+        }
+        if self.current == self.players.len() {
+            self.current = 0;
+            self.day += 1;
+        }
+        true
+    }
+
+    /// Passes the turn to the next player, returns true when a new day starts
+    pub fn advance(&mut self) -> bool {
+        self.current = (self.current + 1) % self.players.len();
+        if self.current == 0 {
+            self.day += 1;
+        }
+        self.current == 0
+    }
+}
+
+#[derive(Message, Debug, Clone, Copy, PartialEq)]
+pub struct TurnStarted {
+    pub player: PlayerId,
+    pub day: u32,
+}
+
+#[derive(Message, Debug, Clone, Copy, PartialEq)]
+pub struct TurnEnded {
+    pub player: PlayerId,
+    pub day: u32,
+}
+
+#[derive(Message, Debug, Clone, Copy, PartialEq)]
+pub struct DayAdvanced {
+    pub day: u32,
+}
+
+/// Asks to finish the turn of the current player
+#[derive(Message, Debug, Clone, Copy, PartialEq)]
+pub struct EndTurn;
+
+/// The current player gives up the match
+#[derive(Message, Debug, Clone, Copy, PartialEq)]
+pub struct Resign;
+
+#[derive(Message, Debug, Clone, Copy, PartialEq)]
+pub struct PlayerDefeated {
+    pub player: PlayerId,
+    pub reason: DefeatReason,
+}
+
+impl Plugin for TurnPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_sub_state::<TurnPhase>()
+            .add_message::<TurnStarted>()
+            .add_message::<TurnEnded>()
+            .add_message::<DayAdvanced>()
+            .add_message::<EndTurn>()
+            .add_message::<Resign>()
+            .add_message::<PlayerDefeated>()
+            .add_systems(
+                OnEnter(BoardLoad::Complete),
+                setup_turns
+                    .after(Board::spawn_terrain)
+                    .run_if(in_state(GameState::InGame)),
+            )
+            .add_systems(OnExit(GameState::InGame), remove_turns)
+            .add_systems(
+                Update,
+                (
+                    start_turn.run_if(in_state(TurnPhase::Upkeep)),
+                    collect_upkeep.after(start_turn),
+                    on_end_turn.run_if(in_state(TurnPhase::Command)),
+                    finish_turn.run_if(in_state(TurnPhase::End)),
+                    check_match_end.after(finish_turn),
+                )
+                    .run_if(resource_exists::<TurnState>)
+                    .run_if(not(resource_exists::<MatchResult>)),
+            );
+    }
+}
+
+fn setup_turns(mut commands: Commands, board: Res<Board>) {
+    let players = board.map().players.iter().map(|player| player.id).collect();
+    commands.insert_resource(TurnState::new(players));
+}
+
+fn remove_turns(mut commands: Commands) {
+    commands.remove_resource::<TurnState>();
+    commands.remove_resource::<MatchResult>();
+}
+
+fn start_turn(
+    mut board: ResMut<Board>,
+    turn: Res<TurnState>,
+    mut started: MessageWriter<TurnStarted>,
+    mut phase: ResMut<NextState<TurnPhase>>,
+) {
+    board.start_turn();
+    started.write(TurnStarted {
+        player: turn.player(),
+        day: turn.day(),
+    });
+    phase.set(TurnPhase::Command);
+}
+
+/// Pays the income and the repairs of the player starting the turn
+fn collect_upkeep(mut board: ResMut<Board>, mut started: MessageReader<TurnStarted>) {
+    for turn in started.read() {
+        let upkeep = board.upkeep(turn.player);
+        bevy::log::info!("Player {} upkeep: {upkeep:?}", turn.player);
+    }
+}
+
+fn on_end_turn(mut end_turn: MessageReader<EndTurn>, mut phase: ResMut<NextState<TurnPhase>>) {
+    if end_turn.read().last().is_some() {
+        phase.set(TurnPhase::End);
+    }
+}
+
+fn finish_turn(
+    mut turn: ResMut<TurnState>,
+    mut ended: MessageWriter<TurnEnded>,
+    mut new_day: MessageWriter<DayAdvanced>,
+    mut phase: ResMut<NextState<TurnPhase>>,
+) {
+    ended.write(TurnEnded {
+        player: turn.player(),
+        day: turn.day(),
+    });
+    if turn.advance() {
+        new_day.write(DayAdvanced { day: turn.day() });
+    }
+    phase.set(TurnPhase::Upkeep);
+}
+
+/// Defeats the players that resigned or lost by the rules after every action and
+/// ends the match when somebody won
+fn check_match_end(
+    mut commands: Commands,
+    mut board: ResMut<Board>,
+    mut turn: ResMut<TurnState>,
+    mut resign: MessageReader<Resign>,
+    (mut ended, mut new_day, mut defeated, mut recolor): (
+        MessageWriter<TurnEnded>,
+        MessageWriter<DayAdvanced>,
+        MessageWriter<PlayerDefeated>,
+        MessageWriter<BuildingCaptured>,
+    ),
+    mut phase: ResMut<NextState<TurnPhase>>,
+    units: Query<(Entity, &BoardPos), With<UnitComponent>>,
+) {
+    let resigned = resign.read().last().is_some();
+    if !resigned && !board.is_changed() && !turn.is_changed() {
+        return;
+    }
+    let mut defeats = board.pending_defeats(turn.day());
+    if resigned {
+        defeats.push((turn.player(), DefeatReason::Resigned));
+    }
+    for (player, reason) in defeats.iter().copied() {
+        bevy::log::info!("Player {player} is defeated: {reason:?}");
+        // The buildings left neutral are tinted again like captured ones
+        for pos in board.defeat(player, reason) {
+            recolor.write(BuildingCaptured(pos));
+        }
+        defeated.write(PlayerDefeated { player, reason });
+        let (current, day) = (turn.player(), turn.day());
+        if turn.remove_player(player) {
+            ended.write(TurnEnded {
+                player: current,
+                day,
+            });
+            if turn.day() != day {
+                new_day.write(DayAdvanced { day: turn.day() });
+            }
+            phase.set(TurnPhase::Upkeep);
+        }
+    }
+    if !defeats.is_empty() {
+        for (entity, position) in units.iter() {
+            if !board.units.contains_key(&position.0) {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+    if let Some(result) = board.check_victory(turn.day()) {
+        bevy::log::info!("The match ended: {result:?}");
+        commands.insert_resource(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advance_turns() {
+        let mut turn = TurnState::new(vec![1, 3]);
+        assert_eq!((turn.player(), turn.day()), (1, 1));
+
+        assert!(!turn.advance());
+        assert_eq!((turn.player(), turn.day()), (3, 1));
+        assert!(turn.advance(), "Every player played, it is a new day");
+        assert_eq!((turn.player(), turn.day()), (1, 2));
+    }
+
+    #[test]
+    fn test_without_players() {
+        let mut turn = TurnState::new(vec![]);
+        assert_eq!(turn.player(), 1);
+        assert!(turn.advance());
+        assert_eq!(turn.day(), 2);
+    }
+
+    #[test]
+    fn test_remove_player() {
+        let mut turn = TurnState::new(vec![1, 2, 3]);
+        assert!(!turn.remove_player(2), "It is not the turn of the player");
+        assert_eq!(turn.players(), &[1, 3]);
+        assert_eq!(turn.player(), 1);
+
+        assert!(turn.remove_player(1));
+        assert_eq!((turn.player(), turn.day()), (3, 1));
+
+        let mut turn = TurnState::new(vec![1, 2, 3]);
+        turn.advance();
+        turn.advance();
+        assert!(turn.remove_player(3));
+        assert_eq!((turn.player(), turn.day()), (1, 2), "The next day starts");
+        assert!(!turn.remove_player(4));
+
+        turn.remove_player(2);
+        assert!(!turn.remove_player(1), "The last player is kept");
+        assert_eq!(turn.players(), &[1]);
+    }
+}
@@ -0,0 +1,149 @@
+use assets_helper::AssetsTrait;
+use bevy::prelude::*;
+use bevy_flair::prelude::*;
+use ui_helpers::prelude::{button_press_system, clean_entities};
+use ui_helpers::{prelude::Action, register_menu};
+
+use crate::{
+    animations::FloatAway,
+    assets::FileAssets,
+    board::{Board, BuildingCaptured, CaptureOutcome, UnitAction},
+    interactive::BoardPos,
+    ui::{GameMenus, combat::ChoosingTarget, fog::FogOfWar, movement::PendingMove},
+};
+
+#[derive(Component)]
+pub struct UnitMenu;
+
+register_menu!(
+    register_unit_menu,
+    GameMenus::UnitActions,
+    UnitMenu,
+    UnitAction,
+    spawn_unit_menu,
+    unit_actions_handler
+);
+
+pub fn open_unit_menu(mut menu: ResMut<NextState<GameMenus>>) {
+    menu.set(GameMenus::UnitActions);
+}
+
+pub fn close_unit_menu(mut menu: ResMut<NextState<GameMenus>>) {
+    menu.set(GameMenus::Board);
+}
+
+pub fn spawn_unit_menu(
+    mut commands: Commands,
+    assets_server: Res<AssetServer>,
+    pending: Option<Res<PendingMove>>,
+) {
+    let Some(pending) = pending else {
+        return;
+    };
+    commands
+        .spawn((
+            Node::default(),
+            NodeStyleSheet::new(FileAssets::MenuStyleUiCss.load(&assets_server)),
+            Name::new("unit-actions"),
+            UnitMenu,
+        ))
+        .with_children(|parent| {
+            for action in pending.actions.iter() {
+                parent.spawn((
+                    Button,
+                    Action::new(*action),
+                    children![Text::new(action.label())],
+                ));
+            }
+        });
+}
+
+pub fn unit_actions_handler(
+    mut commands: Commands,
+    mut actions: MessageReader<UnitAction>,
+    mut board: ResMut<Board>,
+    mut menu: ResMut<NextState<GameMenus>>,
+    mut captured: MessageWriter<BuildingCaptured>,
+    pending: Option<Res<PendingMove>>,
+    fog: Option<Res<FogOfWar>>,
+) {
+    let Some(pending) = pending else {
+        actions.clear();
+        return;
+    };
+    let Some(action) = actions.read().last() else {
+        return;
+    };
+    if *action != UnitAction::Fire {
+        let unit_pos = if pending.onto_ally {
+            pending.from
+        } else {
+            pending.to
+        };
+        board.burn_fuel(unit_pos, pending.cells);
+    }
+    let done = match action {
+        UnitAction::Load => board.load_unit(pending.from, pending.to),
+        UnitAction::Join => board.join_units(pending.from, pending.to),
+        UnitAction::Fire => {
+            let targets = board
+                .units
+                .get(&pending.to)
+                .map(|unit| {
+                    board.attack_targets_seeing(
+                        unit,
+                        pending.to,
+                        pending.from != pending.to,
+                        |cell| fog.as_ref().is_none_or(|fog| fog.sees_unit(&board, cell)),
+                    )
+                })
+                .unwrap_or_default();
+            if !targets.is_empty() {
+                // The move is confirmed once the target is chosen
+                commands.insert_resource(ChoosingTarget { targets });
+                menu.set(GameMenus::ChooseTarget);
+                return;
+            }
+            false
+        }
+        UnitAction::Capture => match board.capture_building(pending.to) {
+            Some(outcome) => {
+                if let CaptureOutcome::Captured(_) = outcome {
+                    captured.write(BuildingCaptured(pending.to));
+                }
+                spawn_capture_notice(&mut commands, pending.to, outcome);
+                true
+            }
+            None => false,
+        },
+        UnitAction::Supply => !board.supply_units(pending.to).is_empty(),
+        UnitAction::Wait => true,
+    };
+    if !done {
+        bevy::log::error!(
+            "{action:?} is not possible from {} to {}",
+            pending.from,
+            pending.to
+        );
+        return;
+    }
+    if pending.onto_ally {
+        commands.entity(pending.entity).despawn();
+    }
+    commands.remove_resource::<PendingMove>();
+}
+
+/// Text rising over the building with the progress of the capture
+fn spawn_capture_notice(commands: &mut Commands, pos: UVec2, outcome: CaptureOutcome) {
+    let text = match outcome {
+        CaptureOutcome::InProgress(points) => format!("{points} left"),
+        CaptureOutcome::Captured(_) => "Captured!".into(),
+    };
+    commands.spawn((
+        Text2d::new(text),
+        TextFont::from_font_size(12.),
+        TextColor(Color::WHITE),
+        Transform::from_translation(BoardPos::from(pos).get_screen_pos(5) + vec3(0., 16., 0.)),
+        FloatAway::new(24., 1.2),
+    ));
+}
@@ -0,0 +1,278 @@
+use assets_helper::AssetsTrait;
+use bevy::prelude::*;
+
+use crate::{
+    animations::{AnimationIndices, AnimationTimer},
+    assets::FileAssets,
+    board::{Board, CombatRng, DamageForecast, MainBoard, TerrainChanged, UnitComponent},
+    interactive::BoardPos,
+    turns::TurnState,
+    ui::{
+        Cursor, GameMenus, GameUI, HoverCell, TileInfo,
+        fog::FogOfWar,
+        movement::{PendingMove, ShownPositions},
+    },
+};
+
+// Span of the tile info where the forecast is written
+const FORECAST_SPAN: usize = 5;
+
+/// Enemies and walls the unit of the pending move can fire at, waiting for the player to pick one
+#[derive(Resource)]
+pub struct ChoosingTarget {
+    pub targets: Vec<UVec2>,
+}
+
+/// Hovered ranged unit whose threat range is shown, when the player enabled it
+#[derive(Resource, Default)]
+pub struct ThreatRange {
+    pub enabled: bool,
+    shown_for: Option<UVec2>,
+}
+
+#[derive(Message)]
+pub struct ShowAttackUi {
+    position: UVec2,
+}
+
+#[derive(Component)]
+struct AttackOption;
+
+/// Cursor shown over the valid targets while choosing one
+#[derive(Component)]
+pub struct AttackCursor;
+
+pub fn setup_attack_cursor(
+    mut commands: Commands,
+    assets: Res<AssetServer>,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+) {
+    let layout = TextureAtlasLayout::from_grid(UVec2::splat(40), 3, 1, None, None);
+    commands.spawn((
+        AttackCursor,
+        Sprite::from_atlas_image(
+            FileAssets::ImagesGameCursorAttack40X40Png.load(&assets),
+            TextureAtlas {
+                layout: texture_atlas_layouts.add(layout),
+                index: 0,
+            },
+        ),
+        GameUI,
+        Transform::from_translation(Vec3::Z * 4.),
+        Visibility::Hidden,
+        AnimationTimer(Timer::from_seconds(0.15, TimerMode::Repeating)),
+        AnimationIndices::new(0, 2),
+    ));
+}
+
+/// Moves the attack cursor over the hovered target and writes the forecast of the
+/// attack in the tile info
+pub fn on_hover_target(
+    mut hover_reader: MessageReader<HoverCell>,
+    board: Res<Board>,
+    (pending, targeting): (Res<PendingMove>, Res<ChoosingTarget>),
+    mut attack_cursor: Single<(&mut Transform, &mut Visibility), With<AttackCursor>>,
+    mut cursor: Single<&mut Visibility, (With<Cursor>, Without<AttackCursor>)>,
+    tile_info: Single<Entity, With<TileInfo>>,
+    mut writer: TextUiWriter,
+) {
+    let Some(cell) = hover_reader.read().last().map(|msg| msg.cell) else {
+        return;
+    };
+    let forecast = targeting
+        .targets
+        .contains(&cell)
+        .then(|| board.forecast(pending.to, cell))
+        .flatten();
+    let (transform, visibility) = &mut *attack_cursor;
+    match forecast {
+        Some(forecast) => {
+            transform.translation = BoardPos::from(cell).get_screen_pos(4);
+            **visibility = Visibility::Inherited;
+            **cursor = Visibility::Hidden;
+            *writer.text(*tile_info, FORECAST_SPAN) = forecast_text(&forecast);
+        }
+        None => {
+            **visibility = Visibility::Hidden;
+            **cursor = Visibility::Inherited;
+            *writer.text(*tile_info, FORECAST_SPAN) = String::new();
+        }
+    }
+}
+
+pub fn hide_forecast(
+    mut attack_cursor: Single<&mut Visibility, With<AttackCursor>>,
+    mut cursor: Single<&mut Visibility, (With<Cursor>, Without<AttackCursor>)>,
+    tile_info: Single<Entity, With<TileInfo>>,
+    mut writer: TextUiWriter,
+) {
+    **attack_cursor = Visibility::Hidden;
+    **cursor = Visibility::Inherited;
+    *writer.text(*tile_info, FORECAST_SPAN) = String::new();
+}
+
+fn forecast_text(forecast: &DamageForecast) -> String {
+    let counter = match forecast.counter {
+        Some(counter) => format!("{}-{}%", counter.min, counter.max),
+        None => "none".into(),
+    };
+    format!(
+        "Damage: {}-{}%\nCounter: {counter}\n",
+        forecast.damage.min, forecast.damage.max
+    )
+}
+
+pub fn on_shown_attack(
+    mut commands: Commands,
+    board_entity: Single<Entity, With<MainBoard>>,
+    mut on_show: MessageReader<ShowAttackUi>,
+    mut current: ResMut<ShownPositions>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for msg in on_show.read() {
+        let board_helper = BoardPos::from(msg.position);
+        commands
+            .entity(board_entity.entity())
+            .with_children(|parent| {
+                let new_entity = parent
+                    .spawn((
+                        AttackOption,
+                        Transform::from_translation(
+                            board_helper.get_screen_pos(0) + vec3(1.0, 1.0, 0.0),
+                        ),
+                        Mesh2d(meshes.add(Rectangle::new(30., 30.))),
+                        MeshMaterial2d(materials.add(Color::linear_rgba(1., 0., 0., 0.3))),
+                    ))
+                    .id();
+                current.attack.push(new_entity);
+            });
+    }
+}
+
+pub fn show_targets(
+    mut commands: Commands,
+    targeting: Res<ChoosingTarget>,
+    mut current: ResMut<ShownPositions>,
+    mut threat: ResMut<ThreatRange>,
+    mut attack_writer: MessageWriter<ShowAttackUi>,
+) {
+    current.reset_attack(&mut commands);
+    threat.shown_for = None;
+    attack_writer.write_batch(
+        targeting
+            .targets
+            .iter()
+            .map(|target| ShowAttackUi { position: *target }),
+    );
+}
+
+pub fn hide_targets(mut commands: Commands, mut current: ResMut<ShownPositions>) {
+    current.reset_attack(&mut commands);
+}
+
+pub fn toggle_threat_range(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut threat: ResMut<ThreatRange>,
+    mut current: ResMut<ShownPositions>,
+) {
+    if keyboard.just_pressed(KeyCode::KeyR) {
+        threat.enabled = !threat.enabled;
+        if !threat.enabled && threat.shown_for.take().is_some() {
+            current.reset_attack(&mut commands);
+        }
+    }
+}
+
+/// Shows the cells threatened by the hovered ranged unit, only the ranges of the
+/// enemies of the current player are shown
+pub fn on_hover_threat(
+    mut commands: Commands,
+    mut hover_reader: MessageReader<HoverCell>,
+    (board, turn, fog): (Res<Board>, Option<Res<TurnState>>, Option<Res<FogOfWar>>),
+    mut threat: ResMut<ThreatRange>,
+    mut current: ResMut<ShownPositions>,
+    mut attack_writer: MessageWriter<ShowAttackUi>,
+) {
+    let Some(cell) = hover_reader.read().last().map(|msg| msg.cell) else {
+        return;
+    };
+    if !threat.enabled {
+        return;
+    }
+    let player = turn.map(|turn| turn.player());
+    let seen = fog.is_none_or(|fog| fog.sees_unit(&board, &cell));
+    let ranged = board.units.get(&cell).is_some_and(|unit| {
+        seen && Some(unit.owner.0) != player
+            && board
+                .rules()
+                .unit(unit.unit_type)
+                .attack_range
+                .is_some_and(|range| range.is_indirect())
+    });
+    let shown_for = ranged.then_some(cell);
+    if shown_for == threat.shown_for {
+        return;
+    }
+    threat.shown_for = shown_for;
+    current.reset_attack(&mut commands);
+    if let Some(pos) = shown_for {
+        attack_writer.write_batch(
+            board
+                .threat_range(pos)
+                .into_iter()
+                .map(|position| ShowAttackUi { position }),
+        );
+    }
+}
+
+/// Fires at the clicked target, the units destroyed in the fight are despawned
+pub fn on_click_target(
+    mut commands: Commands,
+    mouse: Res<ButtonInput<MouseButton>>,
+    cursor: Single<&Cursor>,
+    mut board: ResMut<Board>,
+    (mut rng, mut terrain_changed): (ResMut<CombatRng>, MessageWriter<TerrainChanged>),
+    (pending, targeting): (Res<PendingMove>, Res<ChoosingTarget>),
+    units: Query<(Entity, &BoardPos), With<UnitComponent>>,
+) {
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let target = cursor.position;
+    if !targeting.targets.contains(&target) {
+        return;
+    }
+    board.burn_fuel(pending.to, pending.cells);
+    let terrain = board.get(&target).copied();
+    let Some(result) = board.attack(pending.to, target, || rng.luck()) else {
+        bevy::log::error!("Unit in {} can not attack {target}", pending.to);
+        return;
+    };
+    bevy::log::info!("Attack from {} to {target}: {result:?}", pending.to);
+    if board.get(&target).copied() != terrain {
+        terrain_changed.write(TerrainChanged(target));
+    }
+    for (entity, position) in units.iter() {
+        if !board.units.contains_key(&position.0) {
+            commands.entity(entity).despawn();
+        }
+    }
+    commands.remove_resource::<ChoosingTarget>();
+    commands.remove_resource::<PendingMove>();
+}
+
+/// Goes back to the actions menu without firing
+pub fn on_cancel_target(
+    mut commands: Commands,
+    mouse: Res<ButtonInput<MouseButton>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut menu: ResMut<NextState<GameMenus>>,
+) {
+    if !mouse.just_pressed(MouseButton::Right) && !keyboard.just_pressed(KeyCode::Escape) {
+        return;
+    }
+    commands.remove_resource::<ChoosingTarget>();
+    menu.set(GameMenus::UnitActions);
+}
@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::{
+    board::{Board, MainBoard, UnitComponent},
+    interactive::{BoardPos, PlayerId},
+    turns::TurnState,
+};
+
+const FOG_COLOR: Color = Color::srgba(0., 0., 0., 0.45);
+
+/// Cells the current player sees, only present when the map has fog of war
+#[derive(Resource, Debug, Default)]
+pub struct FogOfWar {
+    player: PlayerId,
+    visible: HashSet<UVec2>,
+}
+
+impl FogOfWar {
+    pub fn is_visible(&self, pos: &UVec2) -> bool {
+        self.visible.contains(pos)
+    }
+
+    /// The current player knows about the unit in the position
+    pub fn sees_unit(&self, board: &Board, pos: &UVec2) -> bool {
+        board.sees_unit(self.player, pos, &self.visible)
+    }
+}
+
+/// Darkens the cell while it is out of the vision of the player
+#[derive(Component)]
+pub struct FogTile;
+
+/// Computes the vision of the current player again when the board or the turn
+/// change
+pub fn update_fog(
+    mut commands: Commands,
+    board: Res<Board>,
+    turn: Res<TurnState>,
+    fog: Option<ResMut<FogOfWar>>,
+) {
+    if !board.has_fog() {
+        return;
+    }
+    let player = turn.player();
+    match fog {
+        Some(mut fog) if board.is_changed() || turn.is_changed() => {
+            fog.player = player;
+            fog.visible = board.visible_cells(player);
+        }
+        Some(_) => {}
+        None => commands.insert_resource(FogOfWar {
+            player,
+            visible: board.visible_cells(player),
+        }),
+    }
+}
+
+pub fn remove_fog(mut commands: Commands) {
+    commands.remove_resource::<FogOfWar>();
+}
+
+/// Covers every cell with a dark square between the terrain and the units
+pub fn spawn_fog_tiles(
+    mut commands: Commands,
+    board: Res<Board>,
+    board_entity: Single<Entity, With<MainBoard>>,
+) {
+    let (width, height) = board.get_size();
+    commands
+        .entity(board_entity.entity())
+        .with_children(|parent| {
+            for y in 0..height {
+                for x in 0..width {
+                    let position = BoardPos::from((x, y));
+                    parent.spawn((
+                        FogTile,
+                        Sprite::from_color(FOG_COLOR, Vec2::splat(32.)),
+                        Transform::from_translation(position.get_screen_pos(0) + Vec3::Z * 0.5),
+                        position,
+                    ));
+                }
+            }
+        });
+}
+
+/// Shows the fog over the cells out of vision
+pub fn apply_fog(
+    fog: Res<FogOfWar>,
+    mut tiles: Query<(&BoardPos, &mut Visibility), With<FogTile>>,
+) {
+    for (position, mut visibility) in tiles.iter_mut() {
+        *visibility = if fog.is_visible(&position.0) {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+    }
+}
+
+/// Hides the enemy units the player doesn't see
+pub fn hide_unseen_units(
+    fog: Res<FogOfWar>,
+    board: Res<Board>,
+    mut units: Query<(&BoardPos, &mut Visibility), With<UnitComponent>>,
+) {
+    for (position, mut visibility) in units.iter_mut() {
+        *visibility = if fog.sees_unit(&board, &position.0) {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
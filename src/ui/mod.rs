@@ -1,19 +1,52 @@
 use assets_helper::AssetsTrait;
 use bevy::prelude::*;
 use bevy_flair::style::components::NodeStyleSheet;
-use ui_helpers::prelude::clean_entities;
+use ui_helpers::prelude::{button_press_system, clean_entities};
 
+mod actions;
+mod combat;
+mod fog;
 mod movement;
+mod production;
+mod results;
+mod turns;
 
 use crate::{
     GameState,
     animations::{AnimationIndices, AnimationTimer},
     assets::FileAssets,
-    board::{Board, BoardLoad, ShowBoard}, ui::movement::{ShowMovementUi, ShownPositions, apply_visibility_delayed, on_click_cursor, on_shown_movement},
+    board::{Board, BoardLoad, MatchResult, ShowBoard},
+    turns::{EndTurn, Resign, TurnPhase, TurnState},
+    ui::actions::{close_unit_menu, open_unit_menu, register_unit_menu, unit_actions_handler},
+    ui::combat::{
+        ChoosingTarget, ShowAttackUi, ThreatRange, hide_forecast, hide_targets, on_cancel_target,
+        on_click_target, on_hover_target, on_hover_threat, on_shown_attack, setup_attack_cursor,
+        show_targets, toggle_threat_range,
+    },
+    ui::fog::{FogOfWar, apply_fog, hide_unseen_units, remove_fog, spawn_fog_tiles, update_fog},
+    ui::movement::{
+        PendingMove, ShowMovementUi, ShownPositions, apply_visibility_delayed, draw_path_arrow,
+        load_arrow_sprites, on_cancel_move, on_click_cursor, on_hover_path, on_shown_movement,
+        on_turn_ended,
+    },
+    ui::production::{on_cancel_build, on_click_building, register_build_menu},
+    ui::results::{open_results, register_results_menu},
+    ui::turns::{setup_turn_ui, update_turn_info},
 };
 
 pub struct UiPlugin;
 
+#[derive(SubStates, Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[source(GameState = GameState::InGame)]
+pub enum GameMenus {
+    #[default]
+    Board,
+    UnitActions,
+    ChooseTarget,
+    Build,
+    Results,
+}
+
 #[derive(Message)]
 pub struct HoverCell {
     cell: UVec2,
@@ -21,14 +54,106 @@ pub struct HoverCell {
 
 impl Plugin for UiPlugin {
     fn build(&self, app: &mut App) {
-        app
-            .insert_resource(ShownPositions::default())
+        app.add_sub_state::<GameMenus>();
+        register_unit_menu(app);
+        register_build_menu(app);
+        register_results_menu(app);
+        app.insert_resource(ShownPositions::default())
+            .init_resource::<ThreatRange>()
             .add_message::<ShowMovementUi>()
-            .add_systems(OnEnter(GameState::InGame), setup_game_ui)
-            .add_systems(OnExit(GameState::InGame), clean_entities::<GameUI>)
+            .add_message::<ShowAttackUi>()
+            .add_systems(
+                OnEnter(GameState::InGame),
+                (
+                    setup_game_ui,
+                    setup_attack_cursor,
+                    setup_turn_ui,
+                    load_arrow_sprites,
+                ),
+            )
+            .add_systems(
+                OnExit(GameState::InGame),
+                (clean_entities::<GameUI>, remove_fog),
+            )
             .add_systems(Update, update_game_ui.run_if(in_state(BoardLoad::Complete)))
             .add_systems(Update, follow_cursor.run_if(in_state(ShowBoard)))
-            .add_systems(Update, (on_shown_movement, on_click_cursor, apply_visibility_delayed))
+            .add_systems(
+                Update,
+                (
+                    update_fog.run_if(resource_exists::<TurnState>),
+                    spawn_fog_tiles.run_if(resource_added::<FogOfWar>),
+                    (apply_fog, hide_unseen_units).run_if(resource_exists_and_changed::<FogOfWar>),
+                )
+                    .chain()
+                    .run_if(in_state(BoardLoad::Complete)),
+            )
+            .add_systems(
+                Update,
+                (on_shown_movement, on_shown_attack, apply_visibility_delayed),
+            )
+            .add_systems(
+                Update,
+                (
+                    on_click_cursor
+                        .before(unit_actions_handler)
+                        .run_if(in_state(GameMenus::Board)),
+                    on_click_building
+                        .after(on_click_cursor)
+                        .run_if(in_state(GameMenus::Board))
+                        .run_if(in_state(TurnPhase::Command)),
+                    on_cancel_build.run_if(in_state(GameMenus::Build)),
+                    open_unit_menu.run_if(resource_added::<PendingMove>),
+                    close_unit_menu.run_if(resource_removed::<PendingMove>),
+                ),
+            )
+            .add_systems(
+                Update,
+                on_cancel_move
+                    .before(on_click_cursor)
+                    .run_if(in_state(ShowBoard))
+                    .run_if(not(resource_exists::<ChoosingTarget>)),
+            )
+            .add_systems(
+                Update,
+                (
+                    (
+                        on_click_target.before(unit_actions_handler),
+                        on_cancel_target.before(on_cancel_move),
+                        on_hover_target.after(follow_cursor),
+                    )
+                        .run_if(resource_exists::<ChoosingTarget>),
+                    show_targets.run_if(resource_added::<ChoosingTarget>),
+                    (hide_targets, hide_forecast).run_if(resource_removed::<ChoosingTarget>),
+                ),
+            )
+            .add_systems(
+                Update,
+                (toggle_threat_range, on_hover_threat)
+                    .chain()
+                    .run_if(in_state(ShowBoard))
+                    .run_if(not(resource_exists::<PendingMove>)),
+            )
+            .add_systems(
+                Update,
+                (on_hover_path, draw_path_arrow)
+                    .chain()
+                    .after(on_click_cursor)
+                    .run_if(in_state(ShowBoard)),
+            )
+            .add_systems(
+                Update,
+                (
+                    (
+                        button_press_system::<EndTurn>,
+                        button_press_system::<Resign>,
+                    )
+                        .run_if(in_state(TurnPhase::Command))
+                        .run_if(in_state(GameMenus::Board)),
+                    update_turn_info.run_if(resource_exists::<TurnState>),
+                    on_turn_ended.run_if(in_state(GameState::InGame)),
+                    open_results.run_if(resource_added::<MatchResult>),
+                ),
+            )
             .add_message::<HoverCell>();
     }
 }
@@ -121,6 +246,7 @@ fn setup_game_ui(
             TextSpan(format!("\n")),
             TextSpan(format!("\n")),
             TextSpan(format!("\n")),
+            TextSpan::default(),
         ],
     ));
 }
@@ -130,6 +256,7 @@ fn update_game_ui(
     mut writer: TextUiWriter,
     mut hover_reader: MessageReader<HoverCell>,
     board: Res<Board>,
+    fog: Option<Res<FogOfWar>>,
 ) {
     for msg in hover_reader.read() {
         *writer.text(*tile_info, 1) = format!("({},{})\n", msg.cell.x, msg.cell.y);
@@ -140,7 +267,10 @@ fn update_game_ui(
             Some (building) => *writer.text(*tile_info, 3) = format!("{:?}\n", building.build_type),
             None => *writer.text(*tile_info, 3) = "".into(),
         };
-        match board.units.get(&msg.cell) {
+        let seen = fog
+            .as_ref()
+            .is_none_or(|fog| fog.sees_unit(&board, &msg.cell));
+        match board.units.get(&msg.cell).filter(|_| seen) {
             Some(unit) => *writer.text(*tile_info, 4) = format!("{:?}\n", unit.unit_type),
             None => *writer.text(*tile_info, 4) = "".into()
         }
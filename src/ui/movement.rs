@@ -1,8 +1,28 @@
-use bevy::prelude::*;
+use std::f32::consts::FRAC_PI_2;
 
-use crate::{board::{Board, MainBoard, PossibleMovement}, interactive::BoardPos, ui::Cursor};
+use assets_helper::AssetsTrait;
+use bevy::{ecs::system::SystemParam, prelude::*};
 
-const DELAY_FOR_LAYER_TO_SHOW : f32 = 0.05;
+use crate::{
+    animations::MoveAlongPath,
+    assets::FileAssets,
+    board::{
+        Board, Direction, MainBoard, MovementPath, PossibleMovement, ReachableCells, UnitAction,
+        UnitComponent,
+    },
+    interactive::BoardPos,
+    turns::{TurnEnded, TurnState},
+    ui::{Cursor, HoverCell, fog::FogOfWar},
+};
+
+const DELAY_FOR_LAYER_TO_SHOW: f32 = 0.05;
+const SECONDS_PER_MOVED_CELL: f32 = 0.08;
+
+// Frames of the movement hud sheet, they connect with the north of the cell
+const ARROW_HEAD: usize = 0;
+const ARROW_STRAIGHT: usize = 1;
+const ARROW_CORNER: usize = 2;
+const ARROW_TAIL: usize = 3;
 
 #[derive(Message)]
 pub struct ShowMovementUi {
@@ -14,15 +34,16 @@ impl From<PossibleMovement> for ShowMovementUi {
     fn from(value: PossibleMovement) -> Self {
         Self {
             position: value.position,
-            layer: value.layer
+            layer: value.layer,
         }
     }
 }
 
 #[derive(Resource, Default)]
 pub struct ShownPositions {
-    pub movement: Vec<Entity>
-    // attack
+    pub movement: Vec<Entity>,
+    pub path: Vec<Entity>,
+    pub attack: Vec<Entity>,
 }
 
 impl ShownPositions {
@@ -31,22 +52,67 @@ impl ShownPositions {
             cmds.entity(*elem).despawn()
         }
         self.movement = Vec::default();
+        self.reset_path(cmds);
+    }
+
+    pub fn reset_attack(&mut self, cmds: &mut Commands) {
+        for elem in self.attack.iter() {
+            cmds.entity(*elem).despawn()
+        }
+        self.attack = Vec::default();
+    }
+
+    fn reset_path(&mut self, cmds: &mut Commands) {
+        for elem in self.path.iter() {
+            cmds.entity(*elem).despawn()
+        }
+        self.path = Vec::default();
     }
 }
 
+/// Unit chosen by the player, with the cells it can reach and the path it would follow
+#[derive(Resource)]
+pub struct SelectedUnit {
+    pub reachable: ReachableCells,
+    pub path: MovementPath,
+}
+
+/// Unit that moved but the move is not confirmed yet, so it can still go back
+#[derive(Resource)]
+pub struct PendingMove {
+    pub entity: Entity,
+    pub from: UVec2,
+    pub to: UVec2,
+    /// Actions the unit can choose from to confirm the move
+    pub actions: Vec<UnitAction>,
+    /// The unit ends over an ally to load or join it, the board is updated when the
+    /// action is chosen
+    pub onto_ally: bool,
+    /// Cells moved, the unit burns their fuel when the move is confirmed
+    pub cells: u32,
+}
+
+#[derive(Resource)]
+pub struct ArrowSprites {
+    texture: Handle<Image>,
+    layout: Handle<TextureAtlasLayout>,
+}
+
 #[derive(Component)]
 struct MovementOption;
 
+#[derive(Component)]
+struct PathArrow;
+
 #[derive(Component, Deref, DerefMut)]
 pub struct Delay(Timer);
 
-
 pub fn apply_visibility_delayed(
     mut commands: Commands,
     mut entities: Query<(Entity, &mut Delay)>,
-    time: Res<Time>
+    time: Res<Time>,
 ) {
-    for (entity, mut delay) in entities.iter_mut(){
+    for (entity, mut delay) in entities.iter_mut() {
         delay.tick(time.delta());
         if delay.is_finished() {
             let mut entity = commands.entity(entity);
@@ -63,39 +129,340 @@ pub fn on_shown_movement(
     mut current: ResMut<ShownPositions>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
-){
-
+) {
     for msg in on_show.read() {
         let board_helper = BoardPos::from(msg.position);
-        commands.entity(board_entity.entity()).with_children(|parent| {
-            let new_entity = parent.spawn((
-                MovementOption,
-                Transform::from_translation(board_helper.get_screen_pos(0)+vec3(1.0, 1.0, 0.0)),
-                Mesh2d(meshes.add(Rectangle::new(30., 30.))),
-                MeshMaterial2d(materials.add(Color::linear_rgba(0., 0., 1., 0.3))),
-                Visibility::Hidden,
-                Delay(Timer::from_seconds(msg.layer as f32 * DELAY_FOR_LAYER_TO_SHOW, TimerMode::Once))
-            )).id();
-            current.movement.push(new_entity);
-        });
+        commands
+            .entity(board_entity.entity())
+            .with_children(|parent| {
+                let new_entity = parent
+                    .spawn((
+                        MovementOption,
+                        Transform::from_translation(
+                            board_helper.get_screen_pos(0) + vec3(1.0, 1.0, 0.0),
+                        ),
+                        Mesh2d(meshes.add(Rectangle::new(30., 30.))),
+                        MeshMaterial2d(materials.add(Color::linear_rgba(0., 0., 1., 0.3))),
+                        Visibility::Hidden,
+                        Delay(Timer::from_seconds(
+                            msg.layer as f32 * DELAY_FOR_LAYER_TO_SHOW,
+                            TimerMode::Once,
+                        )),
+                    ))
+                    .id();
+                current.movement.push(new_entity);
+            });
     }
 }
 
+/// Movement options and arrow shown for the selected unit
+#[derive(SystemParam)]
+pub struct Selection<'w, 's> {
+    commands: Commands<'w, 's>,
+    shown: ResMut<'w, ShownPositions>,
+    movement_writer: MessageWriter<'w, ShowMovementUi>,
+    turn: Option<Res<'w, TurnState>>,
+    fog: Option<Res<'w, FogOfWar>>,
+}
+
+impl Selection<'_, '_> {
+    fn clear(&mut self) {
+        self.shown.reset(&mut self.commands);
+        self.commands.remove_resource::<SelectedUnit>();
+    }
+
+    fn select_unit(&mut self, board: &Board, pos: UVec2) {
+        let Some(unit) = board.units.get(&pos) else {
+            return;
+        };
+        let other_turn = self
+            .turn
+            .as_ref()
+            .is_some_and(|turn| turn.player() != unit.owner.0);
+        if board.has_moved(&pos) || other_turn {
+            return;
+        }
+        // With fog of war the unit only knows about the enemies the player sees
+        let reachable = unit.get_movements_seeing(pos, board, |cell| {
+            self.fog
+                .as_ref()
+                .is_none_or(|fog| fog.sees_unit(board, cell))
+        });
+        self.movement_writer
+            .write_batch(reachable.iter().map(|mov| (*mov).into()));
+        self.commands.insert_resource(SelectedUnit {
+            path: MovementPath::new(pos),
+            reachable,
+        });
+    }
+}
 
 pub fn on_click_cursor(
-    mut commands: Commands,
+    mut selection: Selection,
     mouse: Res<ButtonInput<MouseButton>>,
-    board: Res<Board>,
+    mut board: ResMut<Board>,
     cursor: Single<&Cursor>,
-    mut shown: ResMut<ShownPositions>,
-    mut movement_writer: MessageWriter<ShowMovementUi>) {
-        if mouse.just_pressed(MouseButton::Left){
-            let pos = cursor.position;
-            shown.reset(&mut commands);
-            let Some(unit) = board.units.get(&pos) else {
-                return
-            };
-            let possible_movements = unit.get_movements(pos,&board);
-            movement_writer.write_batch(possible_movements.into_iter().map(|mov| mov.into()));
+    selected: Option<Res<SelectedUnit>>,
+    pending: Option<Res<PendingMove>>,
+    mut units: Query<(Entity, &mut BoardPos), With<UnitComponent>>,
+) {
+    if mouse.just_pressed(MouseButton::Left) {
+        let pos = cursor.position;
+        // Clicks are ignored until an action is chosen or the move is cancelled
+        if pending.is_some() {
+            return;
         }
+        selection.clear();
+        if let Some(selected) = selected
+            && selected.path.destination() == pos
+            && selected.reachable.can_stop(&pos)
+        {
+            start_move(
+                &mut selection.commands,
+                &mut board,
+                (&selected, selection.fog.as_deref()),
+                &mut units,
+            );
+            return;
+        }
+        selection.select_unit(&board, pos);
+    }
+}
+
+/// Drops the selection of the player that ended the turn
+pub fn on_turn_ended(mut selection: Selection, mut ended: MessageReader<TurnEnded>) {
+    if ended.read().last().is_some() {
+        selection.clear();
+    }
+}
+
+/// Cancels the move waiting to be confirmed, the unit goes back and it is selected
+/// again. Without a pending move the selection is dropped.
+pub fn on_cancel_move(
+    mut selection: Selection,
+    mouse: Res<ButtonInput<MouseButton>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut board: ResMut<Board>,
+    pending: Option<Res<PendingMove>>,
+    mut units: Query<(&mut BoardPos, &mut Transform), With<UnitComponent>>,
+) {
+    if !mouse.just_pressed(MouseButton::Right) && !keyboard.just_pressed(KeyCode::Escape) {
+        return;
+    }
+    selection.clear();
+    let Some(pending) = pending else {
+        return;
+    };
+    selection.commands.remove_resource::<PendingMove>();
+    if !pending.onto_ally && !board.undo_move(pending.from, pending.to) {
+        bevy::log::error!(
+            "Move from {} to {} can not be undone",
+            pending.from,
+            pending.to
+        );
+        return;
+    }
+    if let Ok((mut position, mut transform)) = units.get_mut(pending.entity) {
+        *position = BoardPos::from(pending.from);
+        transform.translation = position.get_screen_pos(1);
+    }
+    selection
+        .commands
+        .entity(pending.entity)
+        .remove::<MoveAlongPath>();
+    selection.select_unit(&board, pending.from);
+}
+
+/// Moves the selected unit following the path of the arrow, with fog of war it can
+/// only fire at the enemies the player sees
+fn start_move(
+    commands: &mut Commands,
+    board: &mut Board,
+    (selected, fog): (&SelectedUnit, Option<&FogOfWar>),
+    units: &mut Query<(Entity, &mut BoardPos), With<UnitComponent>>,
+) {
+    let from = selected.reachable.origin();
+    let Some(unit) = board.units.get(&from).copied() else {
+        return;
+    };
+    let mut path = selected.path.clone();
+    let trapped = path.stop_at_enemies(&unit, board);
+    let to = path.destination();
+    let actions = match trapped {
+        Some(enemy) => {
+            bevy::log::info!("Unit in {from} trapped by the enemy in {enemy}");
+            vec![UnitAction::Wait]
+        }
+        None => board.unit_actions_seeing(from, to, |cell| {
+            fog.is_none_or(|fog| fog.sees_unit(board, cell))
+        }),
+    };
+    let onto_ally = from != to && board.units.contains_key(&to);
+    if !onto_ally && !board.move_unit(from, to) {
+        bevy::log::error!("Unit in {from} can not move to {to}");
+        return;
+    }
+    let Some((entity, mut position)) = units.iter_mut().find(|(_, position)| position.0 == from)
+    else {
+        bevy::log::error!("No unit entity in {from}");
+        return;
+    };
+    *position = BoardPos::from(to);
+    let screen_path = path
+        .cells()
+        .iter()
+        .map(|cell| BoardPos::from(*cell).get_screen_pos(1))
+        .collect();
+    commands
+        .entity(entity)
+        .insert(MoveAlongPath::new(screen_path, SECONDS_PER_MOVED_CELL));
+    commands.insert_resource(PendingMove {
+        entity,
+        from,
+        to,
+        actions,
+        onto_ally,
+        cells: path.cells().len() as u32 - 1,
+    });
+}
+
+pub fn load_arrow_sprites(
+    mut commands: Commands,
+    assets: Res<AssetServer>,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+) {
+    let layout = TextureAtlasLayout::from_grid(UVec2::splat(32), 5, 3, None, None);
+    commands.insert_resource(ArrowSprites {
+        texture: FileAssets::ImagesGameMovementHudPng.load(&assets),
+        layout: texture_atlas_layouts.add(layout),
+    });
+}
+
+pub fn on_hover_path(
+    mut hover_reader: MessageReader<HoverCell>,
+    selected: Option<ResMut<SelectedUnit>>,
+    board: Res<Board>,
+) {
+    let Some(mut selected) = selected else {
+        hover_reader.clear();
+        return;
+    };
+    let mut changed = false;
+    for msg in hover_reader.read() {
+        let SelectedUnit { reachable, path } = selected.bypass_change_detection();
+        changed |= reachable.trace(path, msg.cell, &board);
+    }
+    if changed {
+        selected.set_changed();
+    }
+}
+
+pub fn draw_path_arrow(
+    mut commands: Commands,
+    board_entity: Single<Entity, With<MainBoard>>,
+    selected: Option<Res<SelectedUnit>>,
+    sprites: Res<ArrowSprites>,
+    mut current: ResMut<ShownPositions>,
+) {
+    let Some(selected) = selected else {
+        return;
+    };
+    if !selected.is_changed() {
+        return;
+    }
+    current.reset_path(&mut commands);
+    commands
+        .entity(board_entity.entity())
+        .with_children(|parent| {
+            for (position, index, angle) in arrow_pieces(selected.path.cells()) {
+                let new_entity = parent
+                    .spawn((
+                        PathArrow,
+                        Sprite::from_atlas_image(
+                            sprites.texture.clone(),
+                            TextureAtlas {
+                                layout: sprites.layout.clone(),
+                                index,
+                            },
+                        ),
+                        Transform::from_translation(BoardPos::from(position).get_screen_pos(2))
+                            .with_rotation(Quat::from_rotation_z(angle)),
+                    ))
+                    .id();
+                current.path.push(new_entity);
+            }
+        });
+}
+
+/// Frame and rotation of each cell of the arrow, the frames are rotated counter
+/// clockwise until they connect with the previous and next cells
+fn arrow_pieces(cells: &[UVec2]) -> Vec<(UVec2, usize, f32)> {
+    let mut pieces = Vec::new();
+    if cells.len() < 2 {
+        return pieces;
+    }
+    for (idx, cell) in cells.iter().enumerate() {
+        let previous = idx
+            .checked_sub(1)
+            .and_then(|prev| Direction::between(cell, &cells[prev]));
+        let next = cells
+            .get(idx + 1)
+            .and_then(|next| Direction::between(cell, next));
+        let piece = match (previous, next) {
+            (None, Some(next)) => (ARROW_TAIL, quarters_to(Direction::North, next)),
+            (Some(previous), None) => (ARROW_HEAD, quarters_to(Direction::South, previous)),
+            (Some(previous), Some(next)) if previous.rotate_45(4) == next => {
+                (ARROW_STRAIGHT, quarters_to(Direction::North, next) % 2)
+            }
+            (Some(previous), Some(next)) => {
+                let quarters = (0..4)
+                    .find(|quarters| {
+                        let connects = [
+                            Direction::North.rotate_45(8 - quarters * 2),
+                            Direction::East.rotate_45(8 - quarters * 2),
+                        ];
+                        connects.contains(&previous) && connects.contains(&next)
+                    })
+                    .unwrap_or_default();
+                (ARROW_CORNER, quarters)
+            }
+            (None, None) => continue,
+        };
+        pieces.push((*cell, piece.0, piece.1 as f32 * FRAC_PI_2));
+    }
+    pieces
+}
+
+/// Counter clockwise quarter turns to go from one direction to the other
+fn quarters_to(from: Direction, to: Direction) -> u8 {
+    (0..4)
+        .find(|quarters| from.rotate_45(8 - quarters * 2) == to)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    #[test]
+    fn test_arrow_pieces() {
+        let cells = [uvec2(0, 0), uvec2(1, 0), uvec2(2, 0), uvec2(2, 1)];
+
+        assert_eq!(
+            arrow_pieces(&cells),
+            vec![
+                (uvec2(0, 0), ARROW_TAIL, 3. * FRAC_PI_2),
+                (uvec2(1, 0), ARROW_STRAIGHT, FRAC_PI_2),
+                (uvec2(2, 0), ARROW_CORNER, FRAC_PI_2),
+                (uvec2(2, 1), ARROW_HEAD, 0.),
+            ]
+        );
+        assert_eq!(
+            arrow_pieces(&[uvec2(0, 1), uvec2(0, 0)]),
+            vec![(uvec2(0, 1), ARROW_TAIL, PI), (uvec2(0, 0), ARROW_HEAD, PI)]
+        );
+        assert_eq!(arrow_pieces(&[uvec2(0, 0)]), vec![]);
+    }
 }
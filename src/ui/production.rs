@@ -0,0 +1,145 @@
+use assets_helper::AssetsTrait;
+use bevy::prelude::*;
+use bevy_flair::prelude::*;
+use ui_helpers::prelude::{button_press_system, clean_entities};
+use ui_helpers::{prelude::Action, register_menu};
+
+use crate::{
+    assets::FileAssets,
+    board::{Board, MainBoard, UnitComponent, UnitType, unit_sprite},
+    interactive::BoardPos,
+    turns::TurnState,
+    ui::{
+        Cursor, GameMenus,
+        movement::{PendingMove, SelectedUnit},
+    },
+};
+
+#[derive(Component)]
+pub struct BuildMenu;
+
+#[derive(Message, Debug, Clone, Copy)]
+pub enum BuildAction {
+    Build(UnitType),
+    Cancel,
+}
+
+/// Building where the player is choosing the unit to produce
+#[derive(Resource)]
+pub struct BuildSite(pub UVec2);
+
+register_menu!(
+    register_build_menu,
+    GameMenus::Build,
+    BuildMenu,
+    BuildAction,
+    spawn_build_menu,
+    build_actions_handler
+);
+
+/// Opens the build menu when the player clicks a free building of theirs that
+/// produces units. The click is left to the units while one is selected or moving.
+pub fn on_click_building(
+    mut commands: Commands,
+    mouse: Res<ButtonInput<MouseButton>>,
+    cursor: Single<&Cursor>,
+    board: Res<Board>,
+    turn: Res<TurnState>,
+    mut menu: ResMut<NextState<GameMenus>>,
+    (selected, pending): (Option<Res<SelectedUnit>>, Option<Res<PendingMove>>),
+) {
+    if !mouse.just_pressed(MouseButton::Left) || selected.is_some() || pending.is_some() {
+        return;
+    }
+    let pos = cursor.position;
+    let owned = board
+        .buildings
+        .get(&pos)
+        .is_some_and(|building| building.owner.0 == turn.player());
+    if board.units.contains_key(&pos) || !owned || board.buildable_units(&pos).is_empty() {
+        return;
+    }
+    commands.insert_resource(BuildSite(pos));
+    menu.set(GameMenus::Build);
+}
+
+pub fn on_cancel_build(
+    mut commands: Commands,
+    mouse: Res<ButtonInput<MouseButton>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut menu: ResMut<NextState<GameMenus>>,
+) {
+    if !mouse.just_pressed(MouseButton::Right) && !keyboard.just_pressed(KeyCode::Escape) {
+        return;
+    }
+    commands.remove_resource::<BuildSite>();
+    menu.set(GameMenus::Board);
+}
+
+pub fn spawn_build_menu(
+    mut commands: Commands,
+    assets_server: Res<AssetServer>,
+    board: Res<Board>,
+    turn: Res<TurnState>,
+    site: Option<Res<BuildSite>>,
+) {
+    let Some(site) = site else {
+        return;
+    };
+    commands
+        .spawn((
+            Node::default(),
+            NodeStyleSheet::new(FileAssets::MenuStyleUiCss.load(&assets_server)),
+            Name::new("build-menu"),
+            BuildMenu,
+        ))
+        .with_children(|parent| {
+            for unit_type in board.buildable_units(&site.0) {
+                let label = format!("{unit_type:?} {}", board.rules().unit(unit_type).cost);
+                let mut button = parent.spawn((Button, children![Text::new(label)]));
+                if board.can_build(&site.0, unit_type, turn.player()) {
+                    button.insert(Action::new(BuildAction::Build(unit_type)));
+                } else {
+                    button.insert(ClassList::new("disabled"));
+                }
+            }
+            parent.spawn((
+                Button,
+                Action::new(BuildAction::Cancel),
+                children![Text::new("Cancel")],
+            ));
+        });
+}
+
+pub fn build_actions_handler(
+    mut commands: Commands,
+    mut actions: MessageReader<BuildAction>,
+    mut board: ResMut<Board>,
+    (turn, site): (Res<TurnState>, Option<Res<BuildSite>>),
+    mut menu: ResMut<NextState<GameMenus>>,
+    (assets, mut texture_atlases): (Res<AssetServer>, ResMut<Assets<TextureAtlasLayout>>),
+    board_entity: Single<Entity, With<MainBoard>>,
+) {
+    let Some(site) = site else {
+        actions.clear();
+        return;
+    };
+    let Some(action) = actions.read().last() else {
+        return;
+    };
+    if let BuildAction::Build(unit_type) = *action {
+        if !board.build_unit(site.0, unit_type, turn.player()) {
+            bevy::log::error!("{unit_type:?} can not be built in {}", site.0);
+            return;
+        }
+        let position = BoardPos::from(site.0);
+        commands.entity(*board_entity).with_child((
+            UnitComponent,
+            position,
+            unit_sprite(unit_type, &assets, &mut texture_atlases),
+            Transform::from_translation(position.get_screen_pos(1)),
+        ));
+    }
+    commands.remove_resource::<BuildSite>();
+    menu.set(GameMenus::Board);
+}
@@ -0,0 +1,98 @@
+use assets_helper::AssetsTrait;
+use bevy::prelude::*;
+use bevy_flair::prelude::*;
+use ui_helpers::prelude::{button_press_system, clean_entities};
+use ui_helpers::{prelude::Action, register_menu};
+
+use crate::{
+    GameState,
+    assets::FileAssets,
+    board::{Board, MatchResult},
+    ui::GameMenus,
+};
+
+#[derive(Component)]
+pub struct ResultsMenu;
+
+#[derive(Message, Debug, Clone, Copy)]
+pub enum ResultsAction {
+    BackToMenu,
+}
+
+register_menu!(
+    register_results_menu,
+    GameMenus::Results,
+    ResultsMenu,
+    ResultsAction,
+    spawn_results_menu,
+    results_actions_handler
+);
+
+pub fn open_results(mut menu: ResMut<NextState<GameMenus>>) {
+    menu.set(GameMenus::Results);
+}
+
+pub fn spawn_results_menu(
+    mut commands: Commands,
+    assets_server: Res<AssetServer>,
+    board: Res<Board>,
+    result: Option<Res<MatchResult>>,
+) {
+    let Some(result) = result else {
+        return;
+    };
+    let title = match result.winners.as_slice() {
+        [] => "Draw".to_string(),
+        [player] => format!("Player {player} wins"),
+        players => format!(
+            "Players {} win",
+            players
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    commands
+        .spawn((
+            Node::default(),
+            NodeStyleSheet::new(FileAssets::MenuStyleUiCss.load(&assets_server)),
+            Name::new("results"),
+            ResultsMenu,
+        ))
+        .with_children(|parent| {
+            parent.spawn(Text::new(format!("{title}\n{:?}", result.reason)));
+            for slot in board.map().players.iter() {
+                let stats = board.stats(slot.id);
+                let status = match board.defeat_reason(slot.id) {
+                    Some(reason) => format!("{reason:?}"),
+                    None => "Playing".into(),
+                };
+                parent.spawn(Text::new(format!(
+                    "Player {} ({status}): built {}, lost {}, destroyed {}, captured {}, income {}",
+                    slot.id,
+                    stats.units_built,
+                    stats.units_lost,
+                    stats.units_destroyed,
+                    stats.buildings_captured,
+                    stats.income
+                )));
+            }
+            parent.spawn((
+                Button,
+                Action::new(ResultsAction::BackToMenu),
+                children![Text::new("Back to menu")],
+            ));
+        });
+}
+
+pub fn results_actions_handler(
+    mut actions: MessageReader<ResultsAction>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    for action in actions.read() {
+        match action {
+            ResultsAction::BackToMenu => state.set(GameState::Menus),
+        }
+    }
+}
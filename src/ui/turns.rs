@@ -0,0 +1,48 @@
+use assets_helper::AssetsTrait;
+use bevy::prelude::*;
+use bevy_flair::prelude::*;
+use ui_helpers::prelude::Action;
+
+use crate::{
+    assets::FileAssets,
+    board::Board,
+    turns::{EndTurn, Resign, TurnState},
+    ui::GameUI,
+};
+
+#[derive(Component)]
+pub struct TurnInfo;
+
+pub fn setup_turn_ui(mut commands: Commands, assets: Res<AssetServer>) {
+    commands.spawn((
+        NodeStyleSheet::new(FileAssets::MenuStyleUiCss.load(&assets)),
+        Node::default(),
+        Name::new("turn-info"),
+        GameUI,
+        children![
+            (Text::default(), TurnInfo),
+            (
+                Button,
+                Action::new(EndTurn),
+                children![Text::new("End Turn")],
+            ),
+            (Button, Action::new(Resign), children![Text::new("Resign")]),
+        ],
+    ));
+}
+
+pub fn update_turn_info(
+    turn: Res<TurnState>,
+    board: Res<Board>,
+    mut info: Single<&mut Text, With<TurnInfo>>,
+) {
+    if !turn.is_changed() && !board.is_changed() {
+        return;
+    }
+    info.0 = format!(
+        "Day {} - Player {}\nFunds: {}",
+        turn.day(),
+        turn.player(),
+        board.funds(turn.player())
+    );
+}